@@ -1,3 +1,90 @@
 // Filename: commands/falling.rs
 // Provide what towns will fall into ruins
 
+use crate::commands::pagination::paginate;
+use crate::commands::settings::guild_settings;
+use crate::services::town::{falling_towns, FallingFilter};
+use crate::services::locale::format_money;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
+use chrono::Utc;
+
+/// Towns listed on each page.
+const TOWNS_PER_PAGE: usize = 10;
+
+/// Window used when no hours are given: the next upkeep.
+const DEFAULT_HOURS: u32 = 24;
+
+#[poise::command(slash_command, description_localized("en-US", "List towns that will fall into ruin soon"))]
+pub async fn falling(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "List towns falling within this many hours (default 24)"]
+    #[min = 1]
+    #[max = 720]
+    hours: Option<u32>,
+    #[description = "Only towns in this nation (default: the server's nation)"] nation: Option<String>,
+    #[description = "Only peaceful or only non-peaceful towns"] peaceful: Option<bool>,
+    #[description = "Only towns with at least this many residents"] min_residents: Option<u32>,
+    #[description = "List towns of every nation instead of the server's nation"] all_nations: Option<bool>,
+) -> Result<(), crate::Error> {
+    println!("Falling command used by {}", ctx.author().name);
+    let settings = guild_settings(ctx).await;
+    let source = settings.source_from(&ctx.data().sources);
+    let hours = hours.unwrap_or(DEFAULT_HOURS);
+    let colour = settings.colour();
+    let locale = settings.locale.as_deref();
+    let nation = match all_nations {
+        Some(true) => nation,
+        _ => nation.or(settings.default_nation),
+    };
+    let filter = FallingFilter {
+        nation,
+        peaceful,
+        min_residents: min_residents.map(|n| n as usize),
+    };
+
+    let Some(towns) = ctx.data().towns.standing_towns(&source.name) else {
+        ctx.say("Town data is still loading, try again in a minute").await?;
+        return Ok(());
+    };
+    let falling = falling_towns(towns.iter(), Utc::now(), hours as i64, &filter);
+    let title = format!("Towns falling within {} hours", hours);
+    if falling.is_empty() {
+        let embed = CreateEmbed::default()
+            .title(title)
+            .description("No towns will fall in that time.")
+            .colour(colour);
+        ctx.send(poise::CreateReply::default().embed(embed)).await?;
+        return Ok(());
+    }
+
+    let page_count = falling.len().div_ceil(TOWNS_PER_PAGE);
+    let pages = falling
+        .chunks(TOWNS_PER_PAGE)
+        .enumerate()
+        .map(|(i, page)| {
+            let lines: Vec<String> = page
+                .iter()
+                .map(|(town, falls)| {
+                    format!(
+                        "**{}** ({}) falls <t:{}:R>\n{} bank, {} upkeep, {} residents, at {:.0}, {:.0}",
+                        town.town_name,
+                        town.nation.as_deref().unwrap_or("No nation"),
+                        falls.timestamp(),
+                        format_money(town.bank, locale),
+                        format_money(town.upkeep, locale),
+                        town.residents.len(),
+                        town.coords.0,
+                        town.coords.1,
+                    )
+                })
+                .collect();
+            CreateEmbed::default()
+                .title(title.clone())
+                .description(lines.join("\n\n"))
+                .colour(colour)
+                .footer(CreateEmbedFooter::new(format!("Page {}/{} • {} towns", i + 1, page_count, falling.len())))
+        })
+        .collect();
+
+    paginate(ctx, pages).await
+}
@@ -1,16 +1,18 @@
 // Filename: commands/help.rs
 // Help command
 
-use poise::serenity_prelude::{CreateEmbed, Colour, CreateEmbedFooter};
+use crate::commands::settings::guild_settings;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
 
 #[poise::command(slash_command)]
 pub async fn help(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
     println!("Help command used by {}", ctx.author().name);
+    let colour = guild_settings(ctx).await.colour();
 
     let embed = CreateEmbed::default()
         .title("Help Command")
         .description("This is the help command response.")
-        .colour(Colour::BLITZ_BLUE)
+        .colour(colour)
         .field("Field 1", "This is the first field", false)
         .field("Field 2", "This is the second field", false)
         .footer(CreateEmbedFooter::new("Footer text"))
@@ -1,9 +1,17 @@
 // Filename: commands/mod.rs
 // Entry point for commands modules
 
+pub mod falling;
+pub mod pagination;
 pub mod ping;
+pub mod player;
+pub mod settings;
+pub mod status;
 pub mod help;
+pub mod nation;
+pub mod top;
 pub mod town;
+pub mod watch;
 
 use crate::{Data, Error};
 
@@ -13,5 +21,12 @@ pub fn get_commands() -> Vec<poise::Command<Data, Error>> {
         ping::ping(),
         help::help(),
         town::town(),
+        nation::nation(),
+        falling::falling(),
+        player::player(),
+        top::top(),
+        watch::watch(),
+        settings::settings(),
+        status::status(),
     ]
 }
\ No newline at end of file
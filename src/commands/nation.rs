@@ -0,0 +1,83 @@
+// Filename: commands/nation.rs
+// Retrieves nation information from the database
+
+use crate::commands::pagination::{chunk_list, paginate, FIELD_LIMIT};
+use crate::commands::settings::guild_settings;
+use crate::services::nation::NationService;
+use crate::services::locale::format_money;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
+use chrono::{DateTime, Utc};
+
+#[poise::command(slash_command, description_localized("en-US", "Retrieve information about a nation"))]
+pub async fn nation(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the nation (default: the server's nation)"] nation_name: Option<String>
+) -> Result<(), crate::Error> {
+    println!("Nation command used by {}", ctx.author().name);
+    let client = &ctx.data().db_client;
+    let settings = guild_settings(ctx).await;
+    let service = NationService::new(client, settings.source_from(&ctx.data().sources));
+    let colour = settings.colour();
+    let Some(nation_name) = nation_name.or(settings.default_nation) else {
+        ctx.say("Name a nation, or set this server's nation with /settings nation").await?;
+        return Ok(());
+    };
+
+    match service.get_nation_info(&nation_name).await? {
+        Some(nation) => {
+            let last_updated = DateTime::<Utc>::from_timestamp(nation.last_updated, 0)
+                .expect("Invalid timestamp")
+                .format("%Y-%m-%d")
+                .to_string();
+            let description = match nation.disbanded_at {
+                Some(_) => format!("*{} has disbanded*", nation.nation_name),
+                None => format!("*{} spans {} towns with {} residents*", nation.nation_name, nation.towns.len(), nation.residents),
+            };
+            let next_fall = match (nation.disbanded_at, nation.next_fall, &nation.next_falling_town) {
+                (Some(disbanded_at), _, _) => format!("Disbanded <t:{}:R>", disbanded_at),
+                (None, Some(falls), Some(town)) => format!("{} <t:{}:R>", town, falls),
+                _ => "Never".to_owned(),
+            };
+
+            let mut embed = CreateEmbed::default()
+                .title(format!("The Nation of {}", nation.nation_name))
+                .description(description)
+                .colour(colour)
+                .field("Leader", nation.leader.clone().unwrap_or_else(|| "None".to_owned()), true)
+                .field("Capital", nation.capital.clone().unwrap_or_else(|| "None".to_owned()), true)
+                .field("Residents", nation.residents.to_string(), true)
+                .field("Balance", format_money(nation.bank, settings.locale.as_deref()), true)
+                .field("Chunks", nation.chunks().to_string(), true)
+                .field("Upkeep", format_money(nation.upkeep, settings.locale.as_deref()), true)
+                .field("Next Fall", next_fall, true);
+            if let Some(leader) = &nation.leader {
+                embed = embed.thumbnail(format!("https://mc-heads.net/avatar/{}", leader));
+            }
+
+            // Leave room for the code block around each chunk
+            let towns = chunk_list(&nation.towns, FIELD_LIMIT - 6);
+            let pages = towns
+                .iter()
+                .enumerate()
+                .map(|(page, chunk)| {
+                    let (title, footer) = if towns.len() > 1 {
+                        (
+                            format!("Towns [{}] ({}/{})", nation.towns.len(), page + 1, towns.len()),
+                            format!("Last updated: {} • Page {}/{}", last_updated, page + 1, towns.len()),
+                        )
+                    } else {
+                        (format!("Towns [{}]", nation.towns.len()), format!("Last updated: {}", last_updated))
+                    };
+                    embed.clone()
+                        .field(title, format!("```{}```", chunk), false)
+                        .footer(CreateEmbedFooter::new(footer))
+                })
+                .collect();
+            paginate(ctx, pages).await?;
+        }
+        None => {
+            ctx.say("Nation not found").await?;
+        }
+    }
+    Ok(())
+}
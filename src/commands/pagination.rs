@@ -0,0 +1,172 @@
+// Filename: commands/pagination.rs
+// Previous/Next button navigation shared by commands that list many entries
+
+use poise::serenity_prelude::{
+    ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage,
+};
+use std::time::Duration;
+
+/// How long the buttons keep working after the last press.
+const NAVIGATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Longest text an embed field holds.
+pub const FIELD_LIMIT: usize = 1024;
+
+/// Longest text an embed description holds.
+pub const DESCRIPTION_LIMIT: usize = 4096;
+
+/// A button next to Previous/Next that shows more content to whoever pressed
+/// it, one ephemeral message per embed.
+pub struct Expansion {
+    pub label: String,
+    pub embeds: Vec<CreateEmbed>,
+}
+
+fn navigation(prev_id: &str, next_id: &str, more_id: &str, page: usize, pages: usize, more: Option<&Expansion>) -> Vec<CreateActionRow> {
+    let mut buttons = Vec::new();
+    if pages > 1 {
+        buttons.push(CreateButton::new(prev_id).label("Previous").disabled(page == 0));
+        buttons.push(CreateButton::new(next_id).label("Next").disabled(page + 1 >= pages));
+    }
+    if let Some(more) = more {
+        buttons.push(CreateButton::new(more_id).label(more.label.clone()));
+    }
+    vec![CreateActionRow::Buttons(buttons)]
+}
+
+/// Joins `entries` with commas into as few chunks as possible, none longer
+/// than `limit` bytes. An empty list gives one empty chunk.
+pub fn chunk_list(entries: &[String], limit: usize) -> Vec<String> {
+    let mut chunks = vec![String::new()];
+    for entry in entries {
+        let chunk = chunks.last_mut().expect("chunks is never empty");
+        if chunk.is_empty() {
+            chunk.push_str(entry);
+        } else if chunk.len() + 2 + entry.len() <= limit {
+            chunk.push_str(", ");
+            chunk.push_str(entry);
+        } else {
+            chunks.push(entry.clone());
+        }
+    }
+    chunks
+}
+
+/// One line per entry, cut short with a count of the entries left out, such
+/// as "...and 3 more changes", so the text fits in `limit` bytes.
+pub fn truncate_lines(lines: &[String], limit: usize, noun: &str) -> String {
+    let mut text = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let more = format!("...and {} more{}", lines.len() - i, noun);
+        if text.len() + line.len() + more.len() + 2 > limit {
+            text.push_str(&more);
+            break;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    text
+}
+
+/// Sends the first page and lets the invoking user flip through the rest.
+/// A single page is sent without buttons. The buttons are removed once nobody
+/// has pressed them for `NAVIGATION_TIMEOUT`.
+pub async fn paginate(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    pages: Vec<CreateEmbed>,
+) -> Result<(), crate::Error> {
+    paginate_with(ctx, pages, None).await
+}
+
+/// Like [`paginate`], with an extra `more` button even when there is only one page.
+pub async fn paginate_with(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    pages: Vec<CreateEmbed>,
+    more: Option<Expansion>,
+) -> Result<(), crate::Error> {
+    let Some(first) = pages.first() else {
+        return Ok(());
+    };
+    if pages.len() == 1 && more.is_none() {
+        ctx.send(poise::CreateReply::default().embed(first.clone())).await?;
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let prev_id = format!("{}prev", ctx_id);
+    let next_id = format!("{}next", ctx_id);
+    let more_id = format!("{}more", ctx_id);
+    let buttons = |page: usize| navigation(&prev_id, &next_id, &more_id, page, pages.len(), more.as_ref());
+    let reply = ctx.send(poise::CreateReply::default()
+        .embed(first.clone())
+        .components(buttons(0))
+    ).await?;
+
+    let mut page = 0;
+    let author = ctx.author().id;
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()) && press.user.id == author)
+        .timeout(NAVIGATION_TIMEOUT)
+        .await
+    {
+        if press.data.custom_id == next_id {
+            page = (page + 1).min(pages.len() - 1);
+        } else if press.data.custom_id == prev_id {
+            page = page.saturating_sub(1);
+        } else if let (true, Some(more)) = (press.data.custom_id == more_id, &more) {
+            let mut embeds = more.embeds.iter();
+            let mut message = CreateInteractionResponseMessage::new().ephemeral(true);
+            if let Some(embed) = embeds.next() {
+                message = message.embed(embed.clone());
+            }
+            press.create_response(ctx.serenity_context(), CreateInteractionResponse::Message(message)).await?;
+            for embed in embeds {
+                press.create_followup(
+                    ctx.serenity_context(),
+                    CreateInteractionResponseFollowup::new().ephemeral(true).embed(embed.clone()),
+                ).await?;
+            }
+            continue;
+        } else {
+            continue;
+        }
+
+        press.create_response(
+            ctx.serenity_context(),
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(pages[page].clone())
+                    .components(buttons(page)),
+            ),
+        ).await?;
+    }
+
+    reply.edit(ctx, poise::CreateReply::default()
+        .embed(pages[page].clone())
+        .components(Vec::new())
+    ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_lists_within_the_limit() {
+        let names: Vec<String> = ["Steve", "Alex", "Notch", "Jeb"].iter().map(|&n| n.to_owned()).collect();
+        assert_eq!(chunk_list(&names, 100), ["Steve, Alex, Notch, Jeb"]);
+        assert_eq!(chunk_list(&names, 12), ["Steve, Alex", "Notch, Jeb"]);
+        assert_eq!(chunk_list(&names, 5), ["Steve", "Alex", "Notch", "Jeb"]);
+        assert_eq!(chunk_list(&[], 5), [""]);
+    }
+
+    #[test]
+    fn truncates_lines_with_a_count_of_the_rest() {
+        let lines: Vec<String> = (1..=5).map(|i| format!("Line {}", i)).collect();
+        assert_eq!(truncate_lines(&lines, 100, ""), "Line 1\nLine 2\nLine 3\nLine 4\nLine 5\n");
+        assert_eq!(truncate_lines(&lines, 40, " changes"), "Line 1\nLine 2\n...and 3 more changes");
+        assert_eq!(truncate_lines(&[], 10, ""), "");
+    }
+}
@@ -0,0 +1,67 @@
+// Filename: commands/player.rs
+// Looks up where a player lives and which towns trust them
+
+use crate::commands::pagination::{chunk_list, paginate, FIELD_LIMIT};
+use crate::commands::settings::guild_settings;
+use crate::services::player::PlayerService;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
+use chrono::{DateTime, Utc};
+
+#[poise::command(slash_command, description_localized("en-US", "Retrieve information about a player"))]
+pub async fn player(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the player"] player_name: String
+) -> Result<(), crate::Error> {
+    println!("Player command used by {}", ctx.author().name);
+    let client = &ctx.data().db_client;
+    let settings = guild_settings(ctx).await;
+    let service = PlayerService::new(client, settings.source_from(&ctx.data().sources));
+    let colour = settings.colour();
+
+    match service.get_player_info(&player_name).await? {
+        Some(player) => {
+            let last_updated = DateTime::<Utc>::from_timestamp(player.last_updated, 0)
+                .expect("Invalid timestamp")
+                .format("%Y-%m-%d")
+                .to_string();
+            let description = match (&player.town, player.role) {
+                (Some(town), Some(role)) => format!("*{} is a {} of {}*", player.player_name, role.title(), town),
+                _ => format!("*{} does not live in any town*", player.player_name),
+            };
+            let embed = CreateEmbed::default()
+                .title(player.player_name.clone())
+                .description(description)
+                .colour(colour)
+                .thumbnail(format!("https://mc-heads.net/avatar/{}", player.player_name))
+                .field("Town", player.town.clone().unwrap_or_else(|| "None".to_owned()), true)
+                .field("Nation", player.nation.clone().unwrap_or_else(|| "None".to_owned()), true)
+                .field("Role", player.role.map_or("None", |role| role.title()), true);
+
+            // Leave room for the code block around each chunk
+            let trusted_in = chunk_list(&player.trusted_in, FIELD_LIMIT - 6);
+            let pages = trusted_in
+                .iter()
+                .enumerate()
+                .map(|(page, chunk)| {
+                    let value = if player.trusted_in.is_empty() { "None".to_owned() } else { format!("```{}```", chunk) };
+                    let (title, footer) = if trusted_in.len() > 1 {
+                        (
+                            format!("Trusted In [{}] ({}/{})", player.trusted_in.len(), page + 1, trusted_in.len()),
+                            format!("Last updated: {} • Page {}/{}", last_updated, page + 1, trusted_in.len()),
+                        )
+                    } else {
+                        (format!("Trusted In [{}]", player.trusted_in.len()), format!("Last updated: {}", last_updated))
+                    };
+                    embed.clone()
+                        .field(title, value, false)
+                        .footer(CreateEmbedFooter::new(footer))
+                })
+                .collect();
+            paginate(ctx, pages).await?;
+        }
+        None => {
+            ctx.say("Player not found").await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,253 @@
+// Filename: commands/settings.rs
+// Shows and changes the settings of the server the bot is used in
+
+use crate::config::secret::SourceConfig;
+use crate::models::guilds::{GuildSettings, ALWAYS_ENABLED};
+use crate::services::nation::NationService;
+use poise::serenity_prelude::{ChannelId, CreateEmbed, RoleId};
+use chrono::Utc;
+
+/// Locales Discord clients can be set to.
+const LOCALES: [&str; 32] = [
+    "id", "da", "de", "en-GB", "en-US", "es-ES", "es-419", "fr", "hr", "it", "lt", "hu", "nl", "no", "pl", "pt-BR",
+    "ro", "fi", "sv-SE", "vi", "tr", "cs", "el", "bg", "ru", "uk", "hi", "th", "zh-CN", "ja", "zh-TW", "ko",
+];
+
+/// Settings of the server the command runs in. Direct messages, and servers
+/// whose settings cannot be loaded, get the defaults.
+pub async fn guild_settings(ctx: poise::Context<'_, crate::Data, crate::Error>) -> GuildSettings {
+    let Some(guild_id) = ctx.guild_id() else {
+        return GuildSettings::default();
+    };
+    match ctx.data().settings.get(&ctx.data().db_client, guild_id.get()).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("Could not load settings of guild {}: {}", guild_id, e);
+            GuildSettings::new(guild_id.get())
+        }
+    }
+}
+
+/// Map source the server reads from, see `GuildSettings::source_from`.
+pub async fn guild_source(ctx: poise::Context<'_, crate::Data, crate::Error>) -> SourceConfig {
+    guild_settings(ctx).await.source_from(&ctx.data().sources).clone()
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("show", "source", "nation", "alerts", "locale", "role", "colour", "command", "digest"),
+    subcommand_required,
+    description_localized("en-US", "View or change the bot's settings for this server")
+)]
+pub async fn settings(_: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Show this server's settings"))]
+pub async fn show(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    println!("Settings show command used by {}", ctx.author().name);
+    let settings = guild_settings(ctx).await;
+    let disabled = if settings.disabled_commands.is_empty() {
+        "None".to_owned()
+    } else {
+        settings.disabled_commands.iter().map(|name| format!("/{}", name)).collect::<Vec<_>>().join(", ")
+    };
+    let embed = CreateEmbed::default()
+        .title("Server settings")
+        .colour(settings.colour())
+        .field("Source", settings.source_from(&ctx.data().sources).name.clone(), true)
+        .field("Default nation", settings.default_nation.clone().unwrap_or_else(|| "None".to_owned()), true)
+        .field("Alerts channel", settings.alerts_channel.map_or("None".to_owned(), |id| format!("<#{}>", id)), true)
+        .field("Locale", settings.locale.clone().unwrap_or_else(|| "Default".to_owned()), true)
+        .field("Manager role", settings.manager_role.map_or("None".to_owned(), |id| format!("<@&{}>", id)), true)
+        .field("Embed colour", format!("#{:06X}", settings.colour().0), true)
+        .field("Digest", settings.digest_minutes.map_or("Off".to_owned(), |minutes| format!("{} minutes before upkeep", minutes)), true)
+        .field("Disabled commands", disabled, false);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+async fn autocomplete_source(ctx: poise::Context<'_, crate::Data, crate::Error>, partial: &str) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    ctx.data().sources.iter()
+        .map(|source| source.name.clone())
+        .filter(|name| name.to_lowercase().starts_with(&partial))
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Set the map the bot's commands read from"))]
+pub async fn source(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the map source; leave out to use the default"]
+    #[autocomplete = "autocomplete_source"]
+    name: Option<String>,
+) -> Result<(), crate::Error> {
+    println!("Settings source command used by {}", ctx.author().name);
+    if let Some(name) = &name {
+        if !ctx.data().sources.iter().any(|source| &source.name == name) {
+            return reply(ctx, "Unknown source").await;
+        }
+    }
+    update(ctx, |settings| settings.source = name).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Set the nation commands use when none is given"))]
+pub async fn nation(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the nation; leave out to clear"] name: Option<String>,
+) -> Result<(), crate::Error> {
+    println!("Settings nation command used by {}", ctx.author().name);
+    let name = match name {
+        Some(name) => {
+            let service = NationService::new(&ctx.data().db_client, &guild_source(ctx).await);
+            match service.get_nation_info(&name).await? {
+                Some(nation) => Some(nation.nation_name),
+                None => return reply(ctx, "Nation not found").await,
+            }
+        }
+        None => None,
+    };
+    update(ctx, |settings| settings.default_nation = name).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Set the channel server-wide alerts are posted in"))]
+pub async fn alerts(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Channel for alerts; leave out to turn them off"]
+    #[channel_types("Text", "News")]
+    channel: Option<ChannelId>,
+) -> Result<(), crate::Error> {
+    println!("Settings alerts command used by {}", ctx.author().name);
+    update(ctx, |settings| settings.alerts_channel = channel.map(|c| c.get())).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Set the locale amounts of money are written in"))]
+pub async fn locale(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Discord locale such as en-US; leave out to use the default"] locale: Option<String>,
+) -> Result<(), crate::Error> {
+    println!("Settings locale command used by {}", ctx.author().name);
+    let locale = match locale {
+        Some(locale) => match LOCALES.iter().find(|known| known.eq_ignore_ascii_case(&locale)) {
+            Some(known) => Some((*known).to_owned()),
+            None => return reply(ctx, &format!("Unknown locale; use one of {}", LOCALES.join(", "))).await,
+        },
+        None => None,
+    };
+    update(ctx, |settings| settings.locale = locale).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Set the role allowed to change settings"))]
+pub async fn role(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Role allowed to change settings; leave out to allow server managers only"] role: Option<RoleId>,
+) -> Result<(), crate::Error> {
+    println!("Settings role command used by {}", ctx.author().name);
+    update(ctx, |settings| settings.manager_role = role.map(|r| r.get())).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Set the colour of the bot's embeds"))]
+pub async fn colour(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Hex colour such as #3498DB; leave out to use the default"] colour: Option<String>,
+) -> Result<(), crate::Error> {
+    println!("Settings colour command used by {}", ctx.author().name);
+    let colour = match colour {
+        Some(colour) => match parse_colour(&colour) {
+            Some(colour) => Some(colour),
+            None => return reply(ctx, "Colours are written as six hex digits, such as #3498DB").await,
+        },
+        None => None,
+    };
+    update(ctx, |settings| settings.embed_colour = colour).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Turn a command on or off in this server"))]
+pub async fn command(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the command, such as top"] name: String,
+    #[description = "Whether the command can be used"] enabled: bool,
+) -> Result<(), crate::Error> {
+    println!("Settings command command used by {}", ctx.author().name);
+    let name = name.trim_start_matches('/').to_lowercase();
+    if !ctx.framework().options().commands.iter().any(|command| command.name == name) {
+        return reply(ctx, "Unknown command").await;
+    }
+    if ALWAYS_ENABLED.contains(&name.as_str()) {
+        return reply(ctx, &format!("/{} cannot be turned off", name)).await;
+    }
+    update(ctx, |settings| {
+        settings.disabled_commands.retain(|disabled| *disabled != name);
+        if !enabled {
+            settings.disabled_commands.push(name);
+            settings.disabled_commands.sort_unstable();
+        }
+    }).await
+}
+
+#[poise::command(slash_command, guild_only, description_localized("en-US", "Post a daily digest of the server's nation before upkeep"))]
+pub async fn digest(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Minutes before upkeep to post the digest; leave out to stop it"]
+    #[min = 5]
+    #[max = 720]
+    minutes: Option<u32>,
+) -> Result<(), crate::Error> {
+    println!("Settings digest command used by {}", ctx.author().name);
+    let settings = guild_settings(ctx).await;
+    if minutes.is_some() && (settings.alerts_channel.is_none() || settings.default_nation.is_none()) {
+        return reply(ctx, "Set an alerts channel and a nation first; the digest covers that nation and is posted there").await;
+    }
+    update(ctx, |settings| settings.digest_minutes = minutes).await
+}
+
+/// Applies `change` to the server's settings if the author may manage them.
+async fn update(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    change: impl FnOnce(&mut GuildSettings),
+) -> Result<(), crate::Error> {
+    let mut settings = guild_settings(ctx).await;
+    if !can_manage(ctx, &settings).await {
+        return reply(ctx, "You need the Manage Server permission or the manager role to change settings").await;
+    }
+    change(&mut settings);
+    settings.last_updated = Utc::now().timestamp();
+    ctx.data().settings.save(&ctx.data().db_client, settings).await?;
+    reply(ctx, "Settings updated").await
+}
+
+/// Members who can manage the server, or who have the manager role, may change settings.
+async fn can_manage(ctx: poise::Context<'_, crate::Data, crate::Error>, settings: &GuildSettings) -> bool {
+    let Some(member) = ctx.author_member().await else {
+        return false;
+    };
+    member.permissions.is_some_and(|permissions| permissions.manage_guild())
+        || settings.manager_role.is_some_and(|role| member.roles.iter().any(|id| id.get() == role))
+}
+
+async fn reply(ctx: poise::Context<'_, crate::Data, crate::Error>, content: &str) -> Result<(), crate::Error> {
+    ctx.send(poise::CreateReply::default().content(content).ephemeral(true)).await?;
+    Ok(())
+}
+
+fn parse_colour(colour: &str) -> Option<u32> {
+    let hex = colour.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!(parse_colour("#3498DB"), Some(0x3498DB));
+        assert_eq!(parse_colour("ff0000"), Some(0xFF0000));
+        assert_eq!(parse_colour("#FFF"), None);
+        assert_eq!(parse_colour("#GGGGGG"), None);
+    }
+}
@@ -0,0 +1,38 @@
+// Filename: commands/status.rs
+// Shows the health of data ingestion for every map source
+
+use poise::serenity_prelude::{CreateEmbed, Colour, CreateEmbedFooter};
+
+#[poise::command(slash_command, description_localized("en-US", "Show the health of map data ingestion"))]
+pub async fn status(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    println!("Status command used by {}", ctx.author().name);
+    let mut health: Vec<_> = ctx.data().ingestion.snapshot().into_iter().collect();
+    health.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let healthy = health.iter().all(|(_, h)| h.consecutive_failures == 0);
+    let mut embed = CreateEmbed::default()
+        .title("Ingestion Status")
+        .colour(if healthy { Colour::DARK_GREEN } else { Colour::RED })
+        .footer(CreateEmbedFooter::new(format!("{} sources", health.len())));
+
+    if health.is_empty() {
+        embed = embed.description("No ingestion cycle has completed yet.");
+    }
+    for (source, h) in health {
+        let last_success = h.last_success
+            .map_or("Never".to_owned(), |t| format!("<t:{}:R>", t));
+        let duration = h.last_cycle_duration
+            .map_or("-".to_owned(), |d| format!("{:.1}s", d.as_secs_f64()));
+        let mut value = format!(
+            "Last success: {}\nCycle time: {}\nConsecutive failures: {}\nRestarts: {}",
+            last_success, duration, h.consecutive_failures, h.restarts
+        );
+        if let (Some(error), Some(at)) = (h.last_error, h.last_error_at) {
+            value.push_str(&format!("\nLast error <t:{}:R>: ```{}```", at, error.chars().take(300).collect::<String>()));
+        }
+        embed = embed.field(source, value, false);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
\ No newline at end of file
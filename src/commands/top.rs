@@ -0,0 +1,100 @@
+// Filename: commands/top.rs
+// Town leaderboards with rank changes since the previous day
+
+use crate::commands::pagination::paginate;
+use crate::commands::settings::guild_settings;
+use crate::models::towns::Town;
+use crate::services::leaderboard::{Metric, RankedTown};
+use crate::services::locale::format_money;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
+use poise::ChoiceParameter;
+use chrono::Utc;
+
+/// Towns listed on each page.
+const TOWNS_PER_PAGE: usize = 10;
+
+/// Towns listed when no limit is given.
+const DEFAULT_LIMIT: u32 = 10;
+
+#[poise::command(slash_command, description_localized("en-US", "Rank towns by balance, residents, size, age or upkeep"))]
+pub async fn top(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "What to rank towns by"] metric: Metric,
+    #[description = "Only towns in this nation"] nation: Option<String>,
+    #[description = "How many towns to list (default 10)"]
+    #[min = 1]
+    #[max = 100]
+    limit: Option<u32>,
+) -> Result<(), crate::Error> {
+    println!("Top command used by {}", ctx.author().name);
+    let settings = guild_settings(ctx).await;
+    let source = settings.source_from(&ctx.data().sources);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT) as usize;
+    let colour = settings.colour();
+
+    let Some(mut board) = ctx.data().towns.leaderboard(&source.name, metric, nation.as_deref(), Utc::now().timestamp()) else {
+        ctx.say("Town data is still loading, try again in a minute").await?;
+        return Ok(());
+    };
+    let total = board.len();
+    board.truncate(limit);
+
+    let title = match &nation {
+        Some(nation) => format!("{} towns in {}", metric.name(), nation),
+        None => format!("{} towns", metric.name()),
+    };
+    if board.is_empty() {
+        let embed = CreateEmbed::default()
+            .title(title)
+            .description("No towns to rank.")
+            .colour(colour);
+        ctx.send(poise::CreateReply::default().embed(embed)).await?;
+        return Ok(());
+    }
+
+    let page_count = board.len().div_ceil(TOWNS_PER_PAGE);
+    let pages = board
+        .chunks(TOWNS_PER_PAGE)
+        .enumerate()
+        .map(|(i, page)| {
+            let lines: Vec<String> = page.iter().map(|line| format_line(line, metric, settings.locale.as_deref())).collect();
+            CreateEmbed::default()
+                .title(title.clone())
+                .description(lines.join("\n"))
+                .colour(colour)
+                .footer(CreateEmbedFooter::new(format!(
+                    "Page {}/{} • {} ranked towns • Changes since yesterday",
+                    i + 1,
+                    page_count,
+                    total
+                )))
+        })
+        .collect();
+
+    paginate(ctx, pages).await
+}
+
+fn format_line(line: &RankedTown, metric: Metric, locale: Option<&str>) -> String {
+    let change = match line.change {
+        None => "new".to_owned(),
+        Some(0) => "=".to_owned(),
+        Some(up) if up > 0 => format!("▲{}", up),
+        Some(down) => format!("▼{}", -down),
+    };
+    let town = &line.town;
+    let value = match metric {
+        Metric::Bank => format_money(town.bank, locale),
+        Metric::Residents => format!("{} residents", town.residents),
+        Metric::Area => format!("{} chunks", Town::chunks_in(town.area)),
+        Metric::Age => format!("founded <t:{}:D>", town.founded),
+        Metric::Upkeep => format!("{}/day", format_money(town.upkeep, locale)),
+    };
+    format!(
+        "**{}.** {} ({}) — {} `{}`",
+        line.rank,
+        town.town_name,
+        town.nation.as_deref().unwrap_or("No nation"),
+        value,
+        change
+    )
+}
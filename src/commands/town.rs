@@ -1,91 +1,266 @@
 // Filename: commands/town.rs
 // Retrieves town information from the database
 
+use crate::commands::pagination::{chunk_list, paginate_with, Expansion, DESCRIPTION_LIMIT, FIELD_LIMIT};
+use crate::commands::settings::{guild_settings, guild_source};
+use crate::models::towns::Town;
+use crate::services::search;
 use crate::services::town::TownService;
-use poise::serenity_prelude::{CreateEmbed, Colour, CreateEmbedFooter};
-use chrono::{DateTime, Utc, Duration};
+use crate::services::forecast::forecast;
+use crate::services::upkeep::fall_time;
+use crate::services::locale::format_money;
+use poise::serenity_prelude::{
+    ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed, Colour,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+};
+use chrono::{DateTime, Duration, Utc};
+
+/// Days of history the balance trend is fitted over.
+const TREND_DAYS: i64 = 7;
+
+/// How far back history is read, so the balance at the start of the trend
+/// window is known even when it last changed well before it.
+const HISTORY_LOOKBACK_DAYS: i64 = 30;
+
+/// Most names Discord shows in an autocomplete list.
+const AUTOCOMPLETE_LIMIT: usize = 25;
+
+/// Closest names offered when no town matches.
+const SUGGESTION_LIMIT: usize = 5;
+
+/// How long the suggestion buttons keep working.
+const SUGGESTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+async fn autocomplete_town(ctx: poise::Context<'_, crate::Data, crate::Error>, partial: &str) -> Vec<String> {
+    let names = ctx.data().towns.town_names(&guild_source(ctx).await.name);
+    search::complete(&names, partial, AUTOCOMPLETE_LIMIT).into_iter().map(str::to_owned).collect()
+}
 
 #[poise::command(slash_command, description_localized("en-US", "Retrieve information about a town"))]
 pub async fn town(
     ctx: poise::Context<'_, crate::Data, crate::Error>, 
-    #[description = "Name of the town"] town_name: String
+    #[description = "Name of the town"]
+    #[autocomplete = "autocomplete_town"]
+    town_name: String
 ) -> Result<(), crate::Error> {
     println!("Town command used by {}", ctx.author().name);
     let client = &ctx.data().db_client;
-    let service = TownService::new(client);
-    
-    fn get_tier_title(residents_count: usize) -> &'static str {
-        match residents_count {
-            1 => "Homestead",
-            2..=5 => "Hamlet",
-            6..=9 => "Village",
-            10..=15 => "Town",
-            16..=21 => "Large Town",
-            22..=27 => "City",
-            28..=35 => "Large City",
-            36..=53 => "Metropolis",
-            54..=77 => "Conurbation",
-            78..=99 => "Megalopolis",
-            _ => "Eperopolis",
-        }
+    let settings = guild_settings(ctx).await;
+    let source = settings.source_from(&ctx.data().sources);
+    let service = TownService::new(client, source);
+    let names = ctx.data().towns.town_names(&source.name);
+    let colour = settings.colour();
+    // Towns only get a new row when they change, so show when the map was last read as well
+    let fetched_at = ctx.data().ingestion.snapshot().get(&source.name).and_then(|health| health.last_success);
+
+    // Fall back to the name index so underscores and spaces match either way
+    let town = match service.get_town_info(&town_name).await? {
+        Some(town) => Some(town),
+        None => match search::exact(&names, &town_name) {
+            Some(name) => service.get_town_info(name).await?,
+            None => None,
+        },
+    };
+    if let Some(town) = town {
+        let (pages, residents) = town_pages(&service, town, colour, settings.locale.as_deref(), fetched_at).await;
+        return paginate_with(ctx, pages, residents).await;
     }
 
-    match service.get_town_info(&town_name).await? {
-        Some(town) => {
-            let culture = if town.culture.is_empty() { "None".to_string() } else { town.culture };
-            let peaceful = if town.peaceful { "✅".to_string() } else { "❌".to_string() };
-            let base_upkeep = 20.0;
-            let total_claims = (town.upkeep - base_upkeep).max(0.0);
-            let last_updated = DateTime::<Utc>::from_timestamp(town.last_updated, 0)
-                .expect("Invalid timestamp")
-                .format("%Y-%m-%d")
-                .to_string();
-            let founded = DateTime::<Utc>::from_timestamp(town.founded, 0)
-                .expect("Invalid timestamp")
-                .format("%Y-%m-%d")
-                .to_string();
-            
-            // Calculate the exact time when the bank balance will fall below zero
-            let now = Utc::now();
-            let upkeep_time = now.date_naive().and_hms_opt(19, 0, 0).expect("Invalid time").and_local_timezone(Utc).unwrap();
-            let mut remaining_balance = town.bank;
-            let mut next_upkeep_time = if now < upkeep_time {
-                upkeep_time
-            } else {
-                upkeep_time + Duration::days(1)
-            };
+    let suggestions = search::closest(&names, &town_name, SUGGESTION_LIMIT);
+    if suggestions.is_empty() {
+        ctx.say("Town not found").await?;
+        return Ok(());
+    }
 
-            while remaining_balance > 0.0 {
-                remaining_balance -= town.upkeep;
-                next_upkeep_time += Duration::days(1);
-            }
+    // Offer the closest names as buttons that open the chosen town
+    let ctx_id = ctx.id();
+    let buttons = suggestions
+        .iter()
+        .enumerate()
+        .map(|(i, name)| CreateButton::new(format!("{}town{}", ctx_id, i)).label(*name))
+        .collect();
+    let reply = ctx.send(poise::CreateReply::default()
+        .content("Town not found. Did you mean:")
+        .components(vec![CreateActionRow::Buttons(buttons)])
+    ).await?;
 
-            let tier_title = get_tier_title(town.residents.len());
-            let embed = CreateEmbed::default()
-                .title(format!("The {} of {}", tier_title, town.town_name))
-                .description(format!("*The {} of {} was founded on {}*", tier_title, town.town_name, founded))
-                .colour(Colour::BLITZ_BLUE)
-                .thumbnail(format!("https://mc-heads.net/avatar/{}", town.mayor))
-                .field("Mayor", town.mayor.clone(), true)
-                .field("Nation", town.nation.unwrap_or_else(|| "None".to_string()), true)
-                .field("Peaceful", peaceful, true)
-                .field("Location", format!("{}, {}", town.coords.0, town.coords.1), true)
-                .field("Balance", format!("${:.2}", town.bank), true)
-                .field("Culture", culture, true)
-                .field("Chunks", format!("{:.2}", total_claims), true)
-                .field("Upkeep", format!("${:.2}", town.upkeep), true)
-                .field("Falls", format!("<t:{}:R>", next_upkeep_time.timestamp()), true)
-                .field(format!("Residents [{}]", town.residents.len()), format!("```{}```", town.residents.join(", ")), false)
-                .field(format!("Trusted [{}]", town.trusted_players.len()), format!("```{}```", town.trusted_players.join(", ")), false)
-                .footer(CreateEmbedFooter::new(format!("Last updated: {}", last_updated)))
-                .to_owned();
-            ctx.send(poise::CreateReply::default()
-                .embed(embed)
+    let author = ctx.author().id;
+    let press = ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()) && press.user.id == author)
+        .timeout(SUGGESTION_TIMEOUT)
+        .await;
+    let chosen = press.as_ref().and_then(|press| {
+        let index = press.data.custom_id.strip_prefix(&format!("{}town", ctx_id))?;
+        suggestions.get(index.parse::<usize>().ok()?)
+    });
+    match (press.as_ref(), chosen) {
+        (Some(press), Some(name)) => {
+            let town = service.get_town_info(name).await?;
+            let content = if town.is_some() { format!("Showing {}", name) } else { "Town not found".to_owned() };
+            press.create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().content(content).components(Vec::new()),
+                ),
             ).await?;
+            if let Some(town) = town {
+                let (pages, residents) = town_pages(&service, town, colour, settings.locale.as_deref(), fetched_at).await;
+                paginate_with(ctx, pages, residents).await?;
+            }
         }
-        None => {
-            ctx.say("Town not found").await?;
+        _ => {
+            reply.edit(ctx, poise::CreateReply::default()
+                .content("Town not found")
+                .components(Vec::new())
+            ).await?;
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn get_tier_title(residents_count: usize) -> &'static str {
+    match residents_count {
+        1 => "Homestead",
+        2..=5 => "Hamlet",
+        6..=9 => "Village",
+        10..=15 => "Town",
+        16..=21 => "Large Town",
+        22..=27 => "City",
+        28..=35 => "Large City",
+        36..=53 => "Metropolis",
+        54..=77 => "Conurbation",
+        78..=99 => "Megalopolis",
+        _ => "Eperopolis",
+    }
+}
+
+/// Builds the /town pages, fitting the balance trend to the town's history.
+/// Resident and trusted lists too long for one field continue on later pages,
+/// and the full resident list is then offered behind a Residents button.
+async fn town_pages(
+    service: &TownService<'_>,
+    town: Town,
+    colour: Colour,
+    locale: Option<&str>,
+    fetched_at: Option<i64>,
+) -> (Vec<CreateEmbed>, Option<Expansion>) {
+    let chunks = format!("{} ({} outposts)", town.chunks(), town.outposts.len());
+    let culture = if town.culture.is_empty() { "None".to_owned() } else { town.culture };
+    let peaceful = if town.peaceful { "✅".to_owned() } else { "❌".to_owned() };
+    let last_changed = DateTime::<Utc>::from_timestamp(town.last_updated, 0)
+        .expect("Invalid timestamp")
+        .format("%Y-%m-%d")
+        .to_string();
+    let status = match fetched_at.and_then(|at| DateTime::<Utc>::from_timestamp(at, 0)) {
+        Some(fetched_at) => format!("Last changed: {} • Map read: {}", last_changed, fetched_at.format("%Y-%m-%d %H:%M UTC")),
+        None => format!("Last changed: {}", last_changed),
+    };
+    let founded = DateTime::<Utc>::from_timestamp(town.founded, 0)
+        .expect("Invalid timestamp")
+        .format("%Y-%m-%d")
+        .to_string();
+    
+    // Fit the balance trend to recorded history, falling back to a projection
+    // that assumes no deposits when there is not enough of it
+    let now = Utc::now();
+    let history = match service.get_history(&town.town_id, now - Duration::days(HISTORY_LOOKBACK_DAYS), now).await {
+        Ok(history) => history,
+        Err(e) => {
+            println!("Failed to load history of {}: {}", town.town_name, e);
+            Vec::new()
+        }
+    };
+    let mut points: Vec<(i64, f64)> = history.iter().map(|point| (point.timestamp, point.bank)).collect();
+    points.push((now.timestamp(), town.bank));
+    let forecast = forecast(&points, now, TREND_DAYS);
+
+    let trend = match &forecast {
+        Some(forecast) => format!(
+            "{}{}/day over {}d",
+            if forecast.daily_net < 0.0 { "-" } else { "+" },
+            format_money(forecast.daily_net.abs(), locale),
+            forecast.days_observed
+        ),
+        None => "Not enough history".to_owned(),
+    };
+    let falls = match (town.fallen_at, &forecast) {
+        (Some(fallen_at), _) if town.renamed_to.is_some() => format!("Renamed <t:{}:R>", fallen_at),
+        (Some(fallen_at), _) if town.merged_into.is_some() => format!("Merged <t:{}:R>", fallen_at),
+        (Some(fallen_at), _) => format!("Fell <t:{}:R>", fallen_at),
+        (None, Some(forecast)) => match (forecast.pessimistic_fall, forecast.optimistic_fall) {
+            (None, _) => "Not on current trend".to_owned(),
+            (Some(worst), None) => format!("<t:{}:R> at worst", worst.timestamp()),
+            (Some(worst), Some(best)) if worst == best => format!("<t:{}:R>", worst.timestamp()),
+            (Some(worst), Some(best)) => format!("<t:{}:R> to <t:{}:R>", worst.timestamp(), best.timestamp()),
+        },
+        (None, None) => match fall_time(town.bank, town.upkeep, now) {
+            Some(falls) => format!("<t:{}:R>", falls.timestamp()),
+            None => "Never".to_owned(),
+        },
+    };
+
+    let tier_title = get_tier_title(town.residents.len());
+    let embed = CreateEmbed::default()
+        .title(format!("The {} of {}", tier_title, town.town_name))
+        .description(match (&town.renamed_to, &town.merged_into, town.fallen_at) {
+            (Some(name), _, _) => format!("*The {} of {} was founded on {} and has since been renamed to {}*", tier_title, town.town_name, founded, name),
+            (_, Some(name), _) => format!("*The {} of {} was founded on {} and has since merged into {}*", tier_title, town.town_name, founded, name),
+            (_, _, Some(_)) => format!("*The {} of {} was founded on {} and has since fallen*", tier_title, town.town_name, founded),
+            _ => format!("*The {} of {} was founded on {}*", tier_title, town.town_name, founded),
+        })
+        .colour(colour)
+        .thumbnail(format!("https://mc-heads.net/avatar/{}", town.mayor))
+        .field("Mayor", town.mayor.clone(), true)
+        .field("Nation", town.nation.unwrap_or_else(|| "None".to_owned()), true)
+        .field("Peaceful", peaceful, true)
+        .field("Location", format!("{:.0}, {:.0}", town.coords.0, town.coords.1), true)
+        .field("Balance", format_money(town.bank, locale), true)
+        .field("Culture", culture, true)
+        .field("Chunks", chunks, true)
+        .field("Upkeep", format_money(town.upkeep, locale), true)
+        .field("Trend", trend, true)
+        .field("Falls", falls, true);
+
+    // Leave room for the code block around each chunk
+    let residents = chunk_list(&town.residents, FIELD_LIMIT - 6);
+    let trusted = chunk_list(&town.trusted_players, FIELD_LIMIT - 6);
+    let page_count = residents.len().max(trusted.len());
+    let list_title = |name: &str, count: usize, part: usize, parts: usize| {
+        if parts > 1 {
+            format!("{} [{}] ({}/{})", name, count, part + 1, parts)
+        } else {
+            format!("{} [{}]", name, count)
+        }
+    };
+    let pages = (0..page_count)
+        .map(|page| {
+            let mut embed = embed.clone();
+            if let Some(chunk) = residents.get(page) {
+                embed = embed.field(list_title("Residents", town.residents.len(), page, residents.len()), format!("```{}```", chunk), false);
+            }
+            if let Some(chunk) = trusted.get(page) {
+                embed = embed.field(list_title("Trusted", town.trusted_players.len(), page, trusted.len()), format!("```{}```", chunk), false);
+            }
+            let footer = if page_count > 1 {
+                format!("{} • Page {}/{}", status, page + 1, page_count)
+            } else {
+                status.clone()
+            };
+            embed.footer(CreateEmbedFooter::new(footer))
+        })
+        .collect();
+
+    let full_list = (residents.len() > 1).then(|| Expansion {
+        label: "Residents".to_owned(),
+        embeds: chunk_list(&town.residents, DESCRIPTION_LIMIT)
+            .into_iter()
+            .map(|chunk| {
+                CreateEmbed::default()
+                    .title(format!("Residents of {} [{}]", town.town_name, town.residents.len()))
+                    .description(chunk)
+                    .colour(colour)
+            })
+            .collect(),
+    });
+    (pages, full_list)
+}
@@ -0,0 +1,245 @@
+// Filename: commands/watch.rs
+// Subscribes users and channels to changes of towns and nations
+
+use crate::commands::settings::{guild_settings, guild_source};
+use crate::services::locale::format_money;
+use crate::models::watches::{Watch, WatchKind};
+use crate::services::nation::NationService;
+use crate::services::search;
+use crate::services::town::TownService;
+use poise::serenity_prelude::{ChannelId, CreateEmbed};
+use chrono::Utc;
+
+/// Conditions a watch can ask about, as given to `/watch town` and `/watch nation`.
+struct Conditions {
+    bank_below: Option<f64>,
+    falls_within: Option<u32>,
+    residents: Option<bool>,
+    mayor: Option<bool>,
+    board: Option<bool>,
+    fallen: Option<bool>,
+}
+
+#[poise::command(slash_command, subcommands("town", "nation", "list", "remove"), subcommand_required, description_localized("en-US", "Get notified when towns or nations change"))]
+pub async fn watch(_: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, description_localized("en-US", "Get notified when a town changes"))]
+#[allow(clippy::too_many_arguments)]
+pub async fn town(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the town"] name: String,
+    #[description = "Notify when the bank drops below this balance"] bank_below: Option<f64>,
+    #[description = "Notify when the town is set to fall within this many hours"]
+    #[min = 1]
+    #[max = 720]
+    falls_within: Option<u32>,
+    #[description = "Notify when residents join or leave"] residents: Option<bool>,
+    #[description = "Notify when the mayor changes"] mayor: Option<bool>,
+    #[description = "Notify when the town board changes"] board: Option<bool>,
+    #[description = "Notify when the town falls, is renamed or merges"] fallen: Option<bool>,
+    #[description = "Post notifications in this channel instead of sending you a direct message"]
+    #[channel_types("Text", "News")]
+    channel: Option<ChannelId>,
+) -> Result<(), crate::Error> {
+    println!("Watch town command used by {}", ctx.author().name);
+    let conditions = Conditions { bank_below, falls_within, residents, mayor, board, fallen };
+    subscribe(ctx, WatchKind::Town, name, conditions, channel).await
+}
+
+#[poise::command(slash_command, description_localized("en-US", "Get notified when any town of a nation changes"))]
+#[allow(clippy::too_many_arguments)]
+pub async fn nation(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Name of the nation"] name: String,
+    #[description = "Notify when a town's bank drops below this balance"] bank_below: Option<f64>,
+    #[description = "Notify when a town is set to fall within this many hours"]
+    #[min = 1]
+    #[max = 720]
+    falls_within: Option<u32>,
+    #[description = "Notify when residents join or leave a town"] residents: Option<bool>,
+    #[description = "Notify when a town's mayor changes"] mayor: Option<bool>,
+    #[description = "Notify when a town board changes"] board: Option<bool>,
+    #[description = "Notify when a town falls, is renamed or merges"] fallen: Option<bool>,
+    #[description = "Post notifications in this channel instead of sending you a direct message"]
+    #[channel_types("Text", "News")]
+    channel: Option<ChannelId>,
+) -> Result<(), crate::Error> {
+    println!("Watch nation command used by {}", ctx.author().name);
+    let conditions = Conditions { bank_below, falls_within, residents, mayor, board, fallen };
+    subscribe(ctx, WatchKind::Nation, name, conditions, channel).await
+}
+
+#[poise::command(slash_command, description_localized("en-US", "List the watches you have set up"))]
+pub async fn list(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    println!("Watch list command used by {}", ctx.author().name);
+    let settings = guild_settings(ctx).await;
+    let source = settings.source_from(&ctx.data().sources);
+    let watches = ctx.data().watches.watches_of(&ctx.data().db_client, source, ctx.author().id.get()).await?;
+    let colour = settings.colour();
+
+    let description = if watches.is_empty() {
+        "You are not watching anything.".to_owned()
+    } else {
+        watches.iter().map(|watch| describe(watch, settings.locale.as_deref())).collect::<Vec<_>>().join("\n")
+    };
+    let embed = CreateEmbed::default()
+        .title("Your watches")
+        .description(description)
+        .colour(colour);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, description_localized("en-US", "Stop watching a town or nation"))]
+pub async fn remove(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Whether the watch is of a town or a nation"] kind: WatchKind,
+    #[description = "Name of the town or nation"] name: String,
+    #[description = "Channel the watch posts in, if it is not sent to you directly"]
+    #[channel_types("Text", "News")]
+    channel: Option<ChannelId>,
+) -> Result<(), crate::Error> {
+    println!("Watch remove command used by {}", ctx.author().name);
+    if channel.is_some() && !can_manage_channels(ctx).await {
+        return deny_channel(ctx).await;
+    }
+    let source = guild_source(ctx).await;
+    // Match the name the watch was stored with, so watches of towns that were
+    // since renamed, or fell and were refounded, can still be removed
+    let user_id = ctx.author().id.get();
+    let channel_id = channel.map(|c| c.get());
+    let watches = ctx.data().watches.watches_of(&ctx.data().db_client, &source, user_id).await?;
+    let mut removed = false;
+    for watch in watches {
+        if watch.kind == kind && watch.channel_id == channel_id && search::normalize(&watch.name) == search::normalize(&name) {
+            removed |= ctx.data().watches.unsubscribe(&ctx.data().db_client, &source, watch.target(), watch.subscriber()).await?;
+        }
+    }
+    let reply = if removed {
+        format!("No longer watching {} {}", kind.as_str(), name)
+    } else {
+        format!("No watch of {} {} was found", kind.as_str(), name)
+    };
+    ctx.send(poise::CreateReply::default().content(reply).ephemeral(true)).await?;
+    Ok(())
+}
+
+async fn subscribe(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    kind: WatchKind,
+    name: String,
+    conditions: Conditions,
+    channel: Option<ChannelId>,
+) -> Result<(), crate::Error> {
+    if channel.is_some() && !can_manage_channels(ctx).await {
+        return deny_channel(ctx).await;
+    }
+
+    // Store the name as the map spells it, and the town's ID so the watch follows renames
+    let settings = guild_settings(ctx).await;
+    let source = settings.source_from(&ctx.data().sources);
+    let (name, town_id) = match kind {
+        WatchKind::Town => {
+            let names = ctx.data().towns.town_names(&source.name);
+            let name = search::exact(&names, &name).map_or(name, str::to_owned);
+            let service = TownService::new(&ctx.data().db_client, source);
+            match service.get_town_info(&name).await? {
+                Some(town) if !town.is_fallen() => (town.town_name, Some(town.town_id)),
+                _ => {
+                    ctx.send(poise::CreateReply::default().content("Town not found").ephemeral(true)).await?;
+                    return Ok(());
+                }
+            }
+        }
+        WatchKind::Nation => {
+            let service = NationService::new(&ctx.data().db_client, source);
+            match service.get_nation_info(&name).await? {
+                Some(nation) => (nation.nation_name, None),
+                None => {
+                    ctx.send(poise::CreateReply::default().content("Nation not found").ephemeral(true)).await?;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    // Without any condition chosen, watch for everything that is not a threshold
+    let Conditions { bank_below, falls_within, residents, mayor, board, fallen } = conditions;
+    let any_chosen = bank_below.is_some()
+        || falls_within.is_some()
+        || [residents, mayor, board, fallen].iter().any(Option::is_some);
+    let default = !any_chosen;
+    let watch = Watch {
+        kind,
+        name,
+        town_id,
+        user_id: ctx.author().id.get(),
+        channel_id: channel.map(|c| c.get()),
+        guild_id: ctx.guild_id().map(|id| id.get()),
+        bank_below,
+        falls_within_hours: falls_within,
+        residents: residents.unwrap_or(default),
+        mayor: mayor.unwrap_or(default),
+        board: board.unwrap_or(default),
+        fallen: fallen.unwrap_or(default),
+        created_at: Utc::now().timestamp(),
+    };
+
+    let confirmation = format!("Now watching {}", describe(&watch, settings.locale.as_deref()));
+    ctx.data().watches.subscribe(&ctx.data().db_client, source, watch).await?;
+    ctx.send(poise::CreateReply::default()
+        .content(confirmation)
+        .ephemeral(true)
+    ).await?;
+    Ok(())
+}
+
+/// Only members who can manage channels may post notifications in one, or stop them.
+async fn can_manage_channels(ctx: poise::Context<'_, crate::Data, crate::Error>) -> bool {
+    match ctx.author_member().await {
+        Some(member) => member.permissions.is_some_and(|permissions| permissions.manage_channels()),
+        None => false,
+    }
+}
+
+async fn deny_channel(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    ctx.send(poise::CreateReply::default()
+        .content("You need the Manage Channels permission to manage notifications in a channel")
+        .ephemeral(true)
+    ).await?;
+    Ok(())
+}
+
+/// One line summing up a watch, for confirmations and `/watch list`.
+fn describe(watch: &Watch, locale: Option<&str>) -> String {
+    let mut conditions = Vec::new();
+    if let Some(bank) = watch.bank_below {
+        conditions.push(format!("bank below {}", format_money(bank, locale)));
+    }
+    if let Some(hours) = watch.falls_within_hours {
+        conditions.push(format!("falling within {}h", hours));
+    }
+    for (enabled, label) in [
+        (watch.residents, "residents"),
+        (watch.mayor, "mayor"),
+        (watch.board, "board"),
+        (watch.fallen, "falls"),
+    ] {
+        if enabled {
+            conditions.push(label.to_owned());
+        }
+    }
+    let delivery = match watch.channel_id {
+        Some(channel_id) => format!("in <#{}>", channel_id),
+        None => "by direct message".to_owned(),
+    };
+    format!(
+        "{} **{}** ({}) {}",
+        watch.kind.as_str(),
+        watch.name,
+        if conditions.is_empty() { "nothing".to_owned() } else { conditions.join(", ") },
+        delivery
+    )
+}
@@ -1,5 +1,5 @@
 // File: config/db_client.rs
-// AWS DynamoDB client creation
+// AWS DynamoDB and S3 client creation
 
 use aws_sdk_dynamodb::Client;
 use aws_sdk_dynamodb::config::Credentials;
@@ -7,14 +7,14 @@ use crate::config::secret::Config;
 use std::env;
 use aws_types::region::Region;
 
-pub async fn create_dynamodb_client() -> Client {
+async fn load_aws_config() -> aws_config::SdkConfig {
     // Load the configuration file
     let config = Config::from_file("config.toml")
         .expect("Failed to load config");
 
     // Check if the application is in development mode
     let is_dev = env::var("APP_ENV")
-        .unwrap_or_else(|_| "production".to_string()) == "development";
+        .unwrap_or_else(|_| "production".to_owned()) == "development";
 
     // Use different configurations based on the environment
     let (aws_access, aws_secret, aws_region, aws_endpoint) = if is_dev {
@@ -43,14 +43,28 @@ pub async fn create_dynamodb_client() -> Client {
     );
 
     // Create the AWS configuration
-    let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+    aws_config::defaults(aws_config::BehaviorVersion::latest())
         .credentials_provider(credentials)
         .region(Region::new(aws_region))
         .endpoint_url(aws_endpoint)
         .load()
-        .await;
+        .await
+}
+
+pub async fn create_dynamodb_client() -> Client {
+    let config = load_aws_config().await;
 
     // Create the DynamoDB client
     let dynamodb_config = aws_sdk_dynamodb::config::Builder::from(&config).build();
     Client::from_conf(dynamodb_config)
+}
+
+pub async fn create_s3_client() -> aws_sdk_s3::Client {
+    let config = load_aws_config().await;
+
+    // Path-style addressing is required by LocalStack and most S3-compatible stores
+    let s3_config = aws_sdk_s3::config::Builder::from(&config)
+        .force_path_style(true)
+        .build();
+    aws_sdk_s3::Client::from_conf(s3_config)
 }
\ No newline at end of file
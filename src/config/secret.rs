@@ -21,6 +21,142 @@ pub struct Config {
     pub aws_region_dev: String,
     pub aws_access_dev: String,
     pub aws_secret_dev: String,
+    // Map sources to ingest
+    #[serde(default = "default_sources")]
+    pub sources: Vec<SourceConfig>,
+    // Raw marker file archive; disabled when absent
+    pub archive: Option<ArchiveConfig>,
+    // Town history retention
+    #[serde(default)]
+    pub history: HistoryConfig,
+}
+
+/// How long town history points are kept, configured as `[history]`.
+/// Points expire through the history table's DynamoDB TTL attribute.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct HistoryConfig {
+    /// Every change point expires after this many days; kept forever when absent
+    pub retention_days: Option<u64>,
+    /// The first point of each UTC day per town is kept this long instead,
+    /// leaving a daily series once the finer points expire; kept forever when absent
+    pub daily_retention_days: Option<u64>,
+}
+
+/// Where raw marker file snapshots are archived, configured as `[archive]`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum ArchiveBackend {
+    /// A directory on local disk
+    Local { path: String },
+    /// An S3-compatible bucket, reached through the AWS endpoint in this file
+    S3 { bucket: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ArchiveConfig {
+    #[serde(flatten)]
+    pub backend: ArchiveBackend,
+    /// Snapshots older than this are deleted; kept forever when absent
+    pub retention_days: Option<u64>,
+}
+
+/// Name of the built-in CCNet source, whose tables keep their unprefixed names.
+pub const DEFAULT_SOURCE: &str = "nationsmap";
+
+/// Shortest poll interval a source may use, so a typo cannot hammer the map server.
+pub const MIN_POLL_INTERVAL_SECS: u64 = 30;
+
+/// Longest source name, keeping the namespaced table names well within DynamoDB's limit.
+const MAX_SOURCE_NAME_LENGTH: usize = 64;
+
+/// One Dynmap world to ingest, configured as a `[[sources]]` entry.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SourceConfig {
+    /// Unique name; also the namespace the source's data is stored under
+    pub name: String,
+    /// Base URL of the Dynmap web UI, e.g. `https://map.ccnetmc.com/nationsmap`
+    pub url: String,
+    #[serde(default = "default_world")]
+    pub world: String,
+    #[serde(default = "default_marker_set")]
+    pub marker_set: String,
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u64,
+    /// Town whose parsed data is printed every cycle, as a quick sanity check
+    pub reference_town: Option<String>,
+}
+
+impl SourceConfig {
+    /// URL of the marker file for this source's world.
+    pub fn marker_url(&self) -> String {
+        format!("{}/tiles/_markers_/marker_{}.json", self.url.trim_end_matches('/'), self.world)
+    }
+
+    /// Checks that the name is safe to use in table names and archive paths,
+    /// and that the poll interval is not too short.
+    fn validate(&self) -> Result<(), String> {
+        let safe = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if self.name.is_empty() || self.name.len() > MAX_SOURCE_NAME_LENGTH || !self.name.chars().all(safe) {
+            return Err(format!(
+                "Source name `{}` must be 1 to {} letters, digits, `_` or `-`",
+                self.name, MAX_SOURCE_NAME_LENGTH
+            ));
+        }
+        if self.poll_interval_secs < MIN_POLL_INTERVAL_SECS {
+            return Err(format!(
+                "Source `{}` polls every {} seconds; the minimum is {}",
+                self.name, self.poll_interval_secs, MIN_POLL_INTERVAL_SECS
+            ));
+        }
+        Ok(())
+    }
+
+    /// Name of `base` table in this source's namespace.
+    pub fn table(&self, base: &str) -> String {
+        if self.name == DEFAULT_SOURCE {
+            base.to_owned()
+        } else {
+            format!("{}_{}", base, self.name)
+        }
+    }
+}
+
+#[cfg(test)]
+impl SourceConfig {
+    /// A source named `test` that is never fetched, for tests.
+    pub fn test_source() -> SourceConfig {
+        SourceConfig {
+            name: "test".to_owned(),
+            url: "http://localhost".to_owned(),
+            world: default_world(),
+            marker_set: default_marker_set(),
+            poll_interval_secs: default_poll_interval(),
+            reference_town: None,
+        }
+    }
+}
+
+fn default_sources() -> Vec<SourceConfig> {
+    vec![SourceConfig {
+        name: DEFAULT_SOURCE.to_owned(),
+        url: "https://map.ccnetmc.com/nationsmap".to_owned(),
+        world: default_world(),
+        marker_set: default_marker_set(),
+        poll_interval_secs: default_poll_interval(),
+        reference_town: Some("Astarte".to_owned()),
+    }]
+}
+
+fn default_world() -> String {
+    "world".to_owned()
+}
+
+fn default_marker_set() -> String {
+    "towny.markerset".to_owned()
+}
+
+fn default_poll_interval() -> u64 {
+    60
 }
 
 impl Config {
@@ -30,6 +166,36 @@ impl Config {
         if config.discord_token.is_empty() {
             return Err("Missing discord_token in config file".into());
         }
+        if config.sources.is_empty() {
+            return Err("At least one [[sources]] entry is required".into());
+        }
+        for source in &config.sources {
+            source.validate()?;
+        }
+        let mut names: Vec<&str> = config.sources.iter().map(|s| s.name.as_str()).collect();
+        names.sort_unstable();
+        if names.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("Source names in config file must be unique".into());
+        }
         Ok(config)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_source_names_and_poll_intervals() {
+        let mut source = default_sources().remove(0);
+        assert!(source.validate().is_ok());
+        for name in ["", "nations map", "../towns", "map.v2"] {
+            source.name = name.to_owned();
+            assert!(source.validate().is_err(), "{} should be rejected", name);
+        }
+        source.name = "earth_2-b".to_owned();
+        assert!(source.validate().is_ok());
+        source.poll_interval_secs = MIN_POLL_INTERVAL_SECS - 1;
+        assert!(source.validate().is_err());
+    }
+}
@@ -10,22 +10,30 @@ mod models;
 mod repositories;
 
 use poise::serenity_prelude as serenity;
-use config::secret::Config;
+use config::secret::{Config, SourceConfig};
 use config::db_client::create_dynamodb_client;
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-    time::Duration,
-};
+use services::archive::SnapshotArchive;
+use services::ingestion::IngestionMonitor;
+use services::leaderboard::TownCache;
+use services::settings::SettingsCache;
+use services::watch::{Notifier, WatchCache};
+use std::{sync::Arc, time::Duration};
 
 // Types used by all command functions
 type Error = Box<dyn std::error::Error + Send + Sync>;
-type Context<'a> = poise::Context<'a, Data, Error>;
 
 // Custom user data passed to all command functions
 pub struct Data {
-    votes: Mutex<HashMap<String, u32>>,
     db_client: aws_sdk_dynamodb::Client,
+    // Every configured source; servers choose theirs, defaulting to the first
+    sources: Vec<SourceConfig>,
+    ingestion: IngestionMonitor,
+    // Standing towns of every source, kept by ingestion for leaderboards and /falling
+    towns: TownCache,
+    // Per-server settings, loaded on first use
+    settings: SettingsCache,
+    // Watches of every source, shared with ingestion and loaded on first use
+    watches: WatchCache,
 }
 
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
@@ -57,6 +65,24 @@ async fn main() {
     // Load the configuration file
     let config = Config::from_file("config.toml").expect("Failed to load config");
 
+    // `replay <path> [source]` ingests marker files from disk instead of starting the bot
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        let Some(path) = args.get(2) else {
+            panic!("Usage: replay <file or directory> [source name]");
+        };
+        let source = match args.get(3) {
+            Some(name) => config.sources.iter().find(|s| &s.name == name)
+                .unwrap_or_else(|| panic!("Unknown source {}", name)),
+            None => &config.sources[0],
+        };
+        let db_client = create_dynamodb_client().await;
+        if let Err(e) = services::replay::replay(source, std::path::Path::new(path), &db_client, &config.history).await {
+            println!("Replay failed: {}", e);
+        }
+        return;
+    }
+
     // Determine if we're in development mode
     let is_development = std::env::var("APP_ENV").unwrap_or_default() == "development";
 
@@ -89,6 +115,16 @@ async fn main() {
                 if ctx.author().id == 123456789 {
                     return Ok(false);
                 }
+                // Servers can turn off commands they do not want
+                let settings = commands::settings::guild_settings(ctx).await;
+                let name = ctx.command().qualified_name.split(' ').next().unwrap_or_default().to_owned();
+                if !settings.is_enabled(&name) {
+                    ctx.send(poise::CreateReply::default()
+                        .content(format!("/{} is turned off in this server", name))
+                        .ephemeral(true)
+                    ).await?;
+                    return Ok(false);
+                }
                 Ok(true)
             })
         }),
@@ -117,17 +153,28 @@ async fn main() {
                     }
                 }
                 
-                // Create the DynamoDB client and start the data fetcher.
+                // Create the DynamoDB client and start one supervised data fetcher per source.
                 let db_client = create_dynamodb_client().await;
-                let db_client_clone = db_client.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = services::data::fetch_data(&db_client_clone).await {
-                        println!("Error in data fetcher: {}", e);
-                    }
-                });
+                let ingestion = IngestionMonitor::default();
+                let towns = TownCache::default();
+                let notifier = Notifier::new(ctx.http.clone());
+                let settings = SettingsCache::default();
+                let watches = WatchCache::default();
+                let archive = match &config.archive {
+                    Some(archive_config) => Some(SnapshotArchive::from_config(archive_config).await),
+                    None => None,
+                };
+                for source in config.sources.clone() {
+                    services::ingestion::spawn(source, db_client.clone(), ingestion.clone(), archive.clone(), config.history.clone(), towns.clone(), watches.clone(), settings.clone(), notifier.clone());
+                }
+                services::digest::spawn(config.sources.clone(), db_client.clone(), settings.clone(), notifier.clone());
                 Ok(Data {
-                    votes: Mutex::new(HashMap::new()),
                     db_client,
+                    sources: config.sources.clone(),
+                    ingestion,
+                    towns,
+                    settings,
+                    watches,
                 })
             })
         })
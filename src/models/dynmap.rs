@@ -0,0 +1,317 @@
+// Filename: models/dynmap.rs
+// Typed model of the Dynmap marker file (marker_world.json)
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The root of a Dynmap `marker_<world>.json` file.
+///
+/// Fields not typed here land in `other` and are checked against the
+/// documented upstream schema by `warnings`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MarkerFile {
+    pub timestamp: Option<i64>,
+    #[serde(default)]
+    pub sets: HashMap<String, MarkerSet>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// A named marker set, e.g. `towny.markerset`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MarkerSet {
+    #[serde(default)]
+    pub areas: HashMap<String, AreaMarker>,
+    #[serde(default)]
+    pub markers: HashMap<String, PointMarker>,
+    #[serde(default)]
+    pub lines: HashMap<String, LineMarker>,
+    #[serde(default)]
+    pub circles: HashMap<String, CircleMarker>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// A filled polygon; Towny uses these for town claims.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AreaMarker {
+    pub label: Option<String>,
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub x: Vec<f64>,
+    #[serde(default)]
+    pub z: Vec<f64>,
+    pub ytop: Option<f64>,
+    pub ybottom: Option<f64>,
+    pub color: Option<String>,
+    pub fillcolor: Option<String>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// A single icon marker; Towny uses these for town homes and outposts.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PointMarker {
+    pub label: Option<String>,
+    pub x: Option<f64>,
+    pub z: Option<f64>,
+    pub icon: Option<String>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// A polyline marker.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct LineMarker {
+    pub label: Option<String>,
+    #[serde(default)]
+    pub x: Vec<f64>,
+    #[serde(default)]
+    pub y: Vec<f64>,
+    #[serde(default)]
+    pub z: Vec<f64>,
+    pub color: Option<String>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// A circle or ellipse marker.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CircleMarker {
+    pub label: Option<String>,
+    pub x: Option<f64>,
+    pub z: Option<f64>,
+    pub xr: Option<f64>,
+    pub zr: Option<f64>,
+    pub color: Option<String>,
+    pub fillcolor: Option<String>,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// Untyped fields of a marker set the upstream schema documents.
+const SET_FIELDS: [&str; 6] = ["label", "hide", "layerprio", "minzoom", "maxzoom", "showlabels"];
+
+/// Untyped fields of an area marker the upstream schema documents.
+const AREA_FIELDS: [&str; 6] = ["markup", "opacity", "fillopacity", "weight", "minzoom", "maxzoom"];
+
+/// Untyped fields of a point marker the upstream schema documents.
+const POINT_FIELDS: [&str; 6] = ["desc", "markup", "y", "dim", "minzoom", "maxzoom"];
+
+/// Untyped fields of a line marker the upstream schema documents.
+const LINE_FIELDS: [&str; 6] = ["desc", "markup", "opacity", "weight", "minzoom", "maxzoom"];
+
+/// Untyped fields of a circle marker the upstream schema documents.
+const CIRCLE_FIELDS: [&str; 8] = ["desc", "markup", "y", "opacity", "fillopacity", "weight", "minzoom", "maxzoom"];
+
+/// Kind of schema deviation found while walking a marker file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaWarningKind {
+    UnknownField(String),
+    MissingField(&'static str),
+    MismatchedVertices { x: usize, z: usize },
+}
+
+/// A schema deviation, located by its path inside the marker file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaWarning {
+    pub path: String,
+    pub kind: SchemaWarningKind,
+}
+
+impl fmt::Display for SchemaWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            SchemaWarningKind::UnknownField(name) => write!(f, "{}: unknown field `{}`", self.path, name),
+            SchemaWarningKind::MissingField(name) => write!(f, "{}: missing field `{}`", self.path, name),
+            SchemaWarningKind::MismatchedVertices { x, z } => {
+                write!(f, "{}: {} x vertices but {} z vertices", self.path, x, z)
+            }
+        }
+    }
+}
+
+impl MarkerFile {
+    /// Walks the whole file and collects every unknown field, missing field
+    /// and malformed polygon.
+    pub fn warnings(&self) -> Vec<SchemaWarning> {
+        let mut warnings = Vec::new();
+        push_unknown(&mut warnings, "root", &self.other, &[]);
+        push_missing(&mut warnings, "root", &[("timestamp", self.timestamp.is_none())]);
+        for (set_id, set) in &self.sets {
+            set.collect_warnings(&format!("sets.{}", set_id), &mut warnings);
+        }
+        warnings
+    }
+}
+
+impl MarkerSet {
+    fn collect_warnings(&self, path: &str, warnings: &mut Vec<SchemaWarning>) {
+        push_unknown(warnings, path, &self.other, &SET_FIELDS);
+        for (id, area) in &self.areas {
+            let path = format!("{}.areas.{}", path, id);
+            push_unknown(warnings, &path, &area.other, &AREA_FIELDS);
+            let missing = [
+                ("label", area.label.is_none()),
+                ("desc", area.desc.is_none()),
+                ("x", area.x.is_empty()),
+                ("z", area.z.is_empty()),
+                ("ytop", area.ytop.is_none()),
+                ("ybottom", area.ybottom.is_none()),
+                ("color", area.color.is_none()),
+                ("fillcolor", area.fillcolor.is_none()),
+            ];
+            push_missing(warnings, &path, &missing);
+            push_mismatched(warnings, &path, &area.x, &area.z);
+        }
+        for (id, marker) in &self.markers {
+            let path = format!("{}.markers.{}", path, id);
+            push_unknown(warnings, &path, &marker.other, &POINT_FIELDS);
+            let missing = [
+                ("label", marker.label.is_none()),
+                ("x", marker.x.is_none()),
+                ("z", marker.z.is_none()),
+                ("icon", marker.icon.is_none()),
+            ];
+            push_missing(warnings, &path, &missing);
+        }
+        for (id, line) in &self.lines {
+            let path = format!("{}.lines.{}", path, id);
+            push_unknown(warnings, &path, &line.other, &LINE_FIELDS);
+            let missing = [
+                ("label", line.label.is_none()),
+                ("x", line.x.is_empty()),
+                ("y", line.y.is_empty()),
+                ("z", line.z.is_empty()),
+                ("color", line.color.is_none()),
+            ];
+            push_missing(warnings, &path, &missing);
+            push_mismatched(warnings, &path, &line.x, &line.z);
+        }
+        for (id, circle) in &self.circles {
+            let path = format!("{}.circles.{}", path, id);
+            push_unknown(warnings, &path, &circle.other, &CIRCLE_FIELDS);
+            let missing = [
+                ("label", circle.label.is_none()),
+                ("x", circle.x.is_none()),
+                ("z", circle.z.is_none()),
+                ("xr", circle.xr.is_none()),
+                ("zr", circle.zr.is_none()),
+                ("color", circle.color.is_none()),
+                ("fillcolor", circle.fillcolor.is_none()),
+            ];
+            push_missing(warnings, &path, &missing);
+        }
+    }
+}
+
+/// Warns about every field in `other` that is not one of the documented `known` fields.
+fn push_unknown(warnings: &mut Vec<SchemaWarning>, path: &str, other: &HashMap<String, Value>, known: &[&str]) {
+    for name in other.keys().filter(|name| !known.contains(&name.as_str())) {
+        warnings.push(SchemaWarning {
+            path: path.to_owned(),
+            kind: SchemaWarningKind::UnknownField(name.clone()),
+        });
+    }
+}
+
+/// Warns about every field of `fields` flagged as missing.
+fn push_missing(warnings: &mut Vec<SchemaWarning>, path: &str, fields: &[(&'static str, bool)]) {
+    for &(name, missing) in fields {
+        if missing {
+            warnings.push(SchemaWarning {
+                path: path.to_owned(),
+                kind: SchemaWarningKind::MissingField(name),
+            });
+        }
+    }
+}
+
+/// Warns when a polygon or polyline has a different number of x and z vertices.
+fn push_mismatched(warnings: &mut Vec<SchemaWarning>, path: &str, x: &[f64], z: &[f64]) {
+    if x.len() != z.len() {
+        warnings.push(SchemaWarning {
+            path: path.to_owned(),
+            kind: SchemaWarningKind::MismatchedVertices { x: x.len(), z: z.len() },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> MarkerFile {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn documented_fields_raise_no_warnings() {
+        let file = parse(r##"{
+            "timestamp": 1733054400000,
+            "sets": {"towny.markerset": {
+                "label": "Towny", "hide": false, "layerprio": 0, "showlabels": true,
+                "areas": {"Rome__0": {"label": "Rome", "desc": "<div></div>", "x": [0, 16], "z": [0, 16],
+                    "ytop": 64, "ybottom": 64, "color": "#FF0000", "fillcolor": "#FF0000", "fillopacity": 0.3,
+                    "weight": 3}},
+                "markers": {"Rome__home": {"label": "Rome", "x": 8, "y": 64, "z": 8, "icon": "blueflag", "dim": "16x16"}},
+                "lines": {"road": {"label": "Road", "x": [0, 16], "y": [64, 64], "z": [0, 0], "color": "#000000"}},
+                "circles": {"ring": {"label": "Ring", "x": 0, "z": 0, "xr": 10, "zr": 10, "color": "#000000",
+                    "fillcolor": "#FFFFFF"}}
+            }}
+        }"##);
+        assert_eq!(file.warnings(), []);
+        let area = &file.sets["towny.markerset"].areas["Rome__0"];
+        assert_eq!((area.ytop, area.fillcolor.as_deref()), (Some(64.0), Some("#FF0000")));
+    }
+
+    #[test]
+    fn warns_about_unknown_fields() {
+        let file = parse(r#"{
+            "timestamp": 1, "version": 2,
+            "sets": {"towny.markerset": {
+                "areas": {"Rome__0": {"desc": "", "x": [0], "z": [0], "tooltip": "hi"}},
+                "lines": {"road": {"x": [0], "z": [0], "dashes": true}}
+            }}
+        }"#);
+        let mut warnings: Vec<String> = file.warnings()
+            .iter()
+            .filter(|w| matches!(w.kind, SchemaWarningKind::UnknownField(_)))
+            .map(|w| w.to_string())
+            .collect();
+        warnings.sort();
+        assert_eq!(warnings, [
+            "root: unknown field `version`",
+            "sets.towny.markerset.areas.Rome__0: unknown field `tooltip`",
+            "sets.towny.markerset.lines.road: unknown field `dashes`",
+        ]);
+    }
+
+    #[test]
+    fn warns_about_missing_fields_and_mismatched_vertices() {
+        let file = parse(r##"{
+            "sets": {"towny.markerset": {
+                "areas": {"Rome__0": {"label": "Rome", "desc": "", "x": [0, 16, 16], "ytop": 64, "color": "#FF0000"}},
+                "markers": {"Rome__home": {"label": "Rome", "x": 8, "icon": "blueflag"}},
+                "circles": {"ring": {"label": "Ring", "x": 0, "z": 0, "xr": 10, "color": "#000000"}}
+            }}
+        }"##);
+        let mut warnings = file.warnings();
+        warnings.sort_by(|a, b| a.path.cmp(&b.path));
+        let set = "sets.towny.markerset";
+        let warning = |path: &str, kind| SchemaWarning { path: format!("{}.{}", set, path), kind };
+        let expected = [
+            SchemaWarning { path: "root".to_owned(), kind: SchemaWarningKind::MissingField("timestamp") },
+            warning("areas.Rome__0", SchemaWarningKind::MissingField("z")),
+            warning("areas.Rome__0", SchemaWarningKind::MissingField("ybottom")),
+            warning("areas.Rome__0", SchemaWarningKind::MissingField("fillcolor")),
+            warning("areas.Rome__0", SchemaWarningKind::MismatchedVertices { x: 3, z: 0 }),
+            warning("circles.ring", SchemaWarningKind::MissingField("zr")),
+            warning("circles.ring", SchemaWarningKind::MissingField("fillcolor")),
+            warning("markers.Rome__home", SchemaWarningKind::MissingField("z")),
+        ];
+        assert_eq!(warnings, expected);
+    }
+}
@@ -0,0 +1,50 @@
+// Filename: models/events.rs
+// Lineage events linking a vanished town to the town that carried it on
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TownEventKind {
+    /// The town continued under a new name, keeping its town ID
+    Rename,
+    /// The town was absorbed by another town that already existed
+    Merge,
+}
+
+impl TownEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TownEventKind::Rename => "rename",
+            TownEventKind::Merge => "merge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TownEvent {
+    /// ID of the town that vanished
+    pub town_id: String,
+    pub kind: TownEventKind,
+    pub from_name: String,
+    pub to_name: String,
+    /// ID of the town that carried on; equal to `town_id` for renames
+    pub target_id: String,
+    /// How closely the two towns matched, from 0.0 to 1.0
+    pub confidence: f64,
+    pub timestamp: i64,
+}
+
+impl TownEvent {
+    pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+        item.insert("town_id".to_owned(), AttributeValue::S(self.town_id.clone()));
+        item.insert("timestamp".to_owned(), AttributeValue::N(self.timestamp.to_string()));
+        item.insert("kind".to_owned(), AttributeValue::S(self.kind.as_str().to_owned()));
+        item.insert("from_name".to_owned(), AttributeValue::S(self.from_name.clone()));
+        item.insert("to_name".to_owned(), AttributeValue::S(self.to_name.clone()));
+        item.insert("target_id".to_owned(), AttributeValue::S(self.target_id.clone()));
+        item.insert("confidence".to_owned(), AttributeValue::N(self.confidence.to_string()));
+        item
+    }
+}
@@ -0,0 +1,170 @@
+// Filename: models/guilds.rs
+// Settings a Discord server has chosen for the bot
+
+use crate::config::secret::SourceConfig;
+use aws_sdk_dynamodb::types::AttributeValue;
+use poise::serenity_prelude::Colour;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Commands that stay available however a server is configured, so settings can always be undone.
+pub const ALWAYS_ENABLED: [&str; 2] = ["settings", "help"];
+
+/// A stored settings row missing an attribute every row has, or holding it in the wrong form.
+#[derive(Debug, thiserror::Error)]
+#[error("stored settings row has a missing or malformed `{0}` attribute")]
+pub struct MalformedSettings(pub &'static str);
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GuildSettings {
+    pub guild_id: u64,
+    /// Name of the map source commands read from; the first configured source when absent
+    pub source: Option<String>,
+    /// Nation commands filter by when none is given
+    pub default_nation: Option<String>,
+    /// Channel server-wide alerts are posted in
+    pub alerts_channel: Option<u64>,
+    /// Discord locale, such as `en-US`, that amounts of money are written for
+    pub locale: Option<String>,
+    /// Role whose members may change settings besides those who can manage the server
+    pub manager_role: Option<u64>,
+    /// Colour of the bot's embeds as `0xRRGGBB`
+    pub embed_colour: Option<u32>,
+    /// Commands turned off in the server, by top-level name
+    pub disabled_commands: Vec<String>,
+    /// How many minutes before upkeep the daily digest is posted; no digest when absent
+    pub digest_minutes: Option<u32>,
+    /// When the last digest was posted, which the next one reports changes since
+    pub last_digest: Option<i64>,
+    pub last_updated: i64,
+}
+
+impl GuildSettings {
+    /// Settings of a server that has not changed any.
+    pub fn new(guild_id: u64) -> Self {
+        Self {
+            guild_id,
+            ..Default::default()
+        }
+    }
+
+    /// The server's source among `sources`, falling back to the first when none
+    /// is chosen or the chosen one is no longer configured.
+    pub fn source_from<'a>(&self, sources: &'a [SourceConfig]) -> &'a SourceConfig {
+        self.source
+            .as_deref()
+            .and_then(|name| sources.iter().find(|source| source.name == name))
+            .unwrap_or(&sources[0])
+    }
+
+    pub fn colour(&self) -> Colour {
+        self.embed_colour.map_or(Colour::BLITZ_BLUE, Colour::new)
+    }
+
+    /// Whether the top-level command `name` may be used in the server.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        ALWAYS_ENABLED.contains(&name) || !self.disabled_commands.iter().any(|disabled| disabled == name)
+    }
+
+    pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
+        let optional_s = |value: Option<&str>| match value {
+            Some(s) => AttributeValue::S(s.to_owned()),
+            None => AttributeValue::Null(true),
+        };
+        let optional_n = |value: Option<String>| match value {
+            Some(n) => AttributeValue::N(n),
+            None => AttributeValue::Null(true),
+        };
+
+        let mut item = HashMap::new();
+        item.insert("guild_id".to_owned(), AttributeValue::N(self.guild_id.to_string()));
+        item.insert("source".to_owned(), optional_s(self.source.as_deref()));
+        item.insert("default_nation".to_owned(), optional_s(self.default_nation.as_deref()));
+        item.insert("alerts_channel".to_owned(), optional_n(self.alerts_channel.map(|id| id.to_string())));
+        item.insert("locale".to_owned(), optional_s(self.locale.as_deref()));
+        item.insert("manager_role".to_owned(), optional_n(self.manager_role.map(|id| id.to_string())));
+        item.insert("embed_colour".to_owned(), optional_n(self.embed_colour.map(|colour| colour.to_string())));
+        if !self.disabled_commands.is_empty() {
+            item.insert("disabled_commands".to_owned(), AttributeValue::Ss(self.disabled_commands.clone()));
+        } else {
+            item.insert("disabled_commands".to_owned(), AttributeValue::Null(true));
+        }
+        item.insert("digest_minutes".to_owned(), optional_n(self.digest_minutes.map(|minutes| minutes.to_string())));
+        item.insert("last_digest".to_owned(), optional_n(self.last_digest.map(|at| at.to_string())));
+        item.insert("timestamp".to_owned(), AttributeValue::N(self.last_updated.to_string()));
+        item
+    }
+
+    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, MalformedSettings> {
+        let s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+
+        Ok(GuildSettings {
+            guild_id: optional_n(item, "guild_id")?.ok_or(MalformedSettings("guild_id"))?,
+            source: s("source"),
+            default_nation: s("default_nation"),
+            alerts_channel: optional_n(item, "alerts_channel")?,
+            locale: s("locale"),
+            manager_role: optional_n(item, "manager_role")?,
+            embed_colour: optional_n(item, "embed_colour")?,
+            // Empty sets are stored as null, as DynamoDB has no empty string set
+            disabled_commands: item.get("disabled_commands")
+                .and_then(|v| v.as_ss().ok())
+                .cloned()
+                .unwrap_or_default(),
+            digest_minutes: optional_n(item, "digest_minutes")?,
+            last_digest: optional_n(item, "last_digest")?,
+            last_updated: optional_n(item, "timestamp")?.ok_or(MalformedSettings("timestamp"))?,
+        })
+    }
+}
+
+/// The number stored as `name`, or `None` when it is absent or null.
+fn optional_n<T: FromStr>(item: &HashMap<String, AttributeValue>, name: &'static str) -> Result<Option<T>, MalformedSettings> {
+    match item.get(name) {
+        Some(v) if !v.is_null() => v.as_n()
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .map(Some)
+            .ok_or(MalformedSettings(name)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_dynamodb() {
+        let settings = GuildSettings {
+            guild_id: 42,
+            source: Some("nationsmap".to_owned()),
+            default_nation: Some("Rome".to_owned()),
+            alerts_channel: Some(7),
+            locale: Some("en-GB".to_owned()),
+            manager_role: None,
+            embed_colour: Some(0xFF0000),
+            disabled_commands: vec!["top".to_owned()],
+            digest_minutes: Some(60),
+            last_digest: None,
+            last_updated: 100,
+        };
+        assert_eq!(GuildSettings::from_dynamodb_item(&settings.to_dynamodb_item()).unwrap(), settings);
+        assert!(!settings.is_enabled("top"));
+        assert!(settings.is_enabled("town"));
+        assert_eq!(GuildSettings::new(42).colour(), Colour::BLITZ_BLUE);
+    }
+
+    #[test]
+    fn rejects_malformed_rows() {
+        let item = GuildSettings::new(42).to_dynamodb_item();
+
+        let mut missing = item.clone();
+        missing.remove("guild_id");
+        assert_eq!(GuildSettings::from_dynamodb_item(&missing).unwrap_err().0, "guild_id");
+
+        let mut malformed = item;
+        malformed.insert("embed_colour".to_owned(), AttributeValue::S("red".to_owned()));
+        assert_eq!(GuildSettings::from_dynamodb_item(&malformed).unwrap_err().0, "embed_colour");
+    }
+}
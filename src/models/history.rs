@@ -0,0 +1,132 @@
+// Filename: models/history.rs
+// Compact point in a town's history, written whenever a tracked field changes
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use std::collections::{HashMap, HashSet};
+use crate::models::towns::Town;
+
+/// A stored history row missing an attribute every row has, or holding it in the wrong form.
+#[derive(Debug, thiserror::Error)]
+#[error("stored history row has a missing or malformed `{0}` attribute")]
+pub struct MalformedSnapshot(pub &'static str);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TownSnapshot {
+    pub town_id: String,
+    pub timestamp: i64,
+    pub town_name: String,
+    pub bank: f64,
+    pub upkeep: f64,
+    pub residents: usize,
+    pub area: f64,
+    pub mayor: String,
+    pub nation: Option<String>,
+    pub board: String,
+    /// Unix time DynamoDB's TTL deletes the point; kept forever when `None`
+    pub expires_at: Option<i64>,
+}
+
+impl TownSnapshot {
+    pub fn from_town(town: &Town) -> Self {
+        Self {
+            town_id: town.town_id.clone(),
+            timestamp: town.last_updated,
+            town_name: town.town_name.clone(),
+            bank: town.bank,
+            upkeep: town.upkeep,
+            residents: town.residents.len(),
+            area: town.area,
+            mayor: town.mayor.clone(),
+            nation: town.nation.clone(),
+            board: town.board.clone(),
+            expires_at: None,
+        }
+    }
+
+    /// Whether any tracked field differs between the two towns. Residents are
+    /// compared by name, so one leaving as another joins still counts.
+    pub fn tracked_fields_differ(old: &Town, new: &Town) -> bool {
+        old.bank != new.bank
+            || old.upkeep != new.upkeep
+            || old.residents.iter().collect::<HashSet<_>>() != new.residents.iter().collect::<HashSet<_>>()
+            || old.area != new.area
+            || old.mayor != new.mayor
+            || old.nation != new.nation
+            || old.board != new.board
+    }
+
+    pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+        item.insert("town_id".to_owned(), AttributeValue::S(self.town_id.clone()));
+        item.insert("timestamp".to_owned(), AttributeValue::N(self.timestamp.to_string()));
+        item.insert("town_name".to_owned(), AttributeValue::S(self.town_name.clone()));
+        item.insert("bank".to_owned(), AttributeValue::N(self.bank.to_string()));
+        item.insert("upkeep".to_owned(), AttributeValue::N(self.upkeep.to_string()));
+        item.insert("residents".to_owned(), AttributeValue::N(self.residents.to_string()));
+        item.insert("area".to_owned(), AttributeValue::N(self.area.to_string()));
+        item.insert("mayor".to_owned(), AttributeValue::S(self.mayor.clone()));
+        item.insert("nation".to_owned(), match &self.nation {
+            Some(n) => AttributeValue::S(n.clone()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("board".to_owned(), AttributeValue::S(self.board.clone()));
+        if let Some(expires_at) = self.expires_at {
+            item.insert("expires_at".to_owned(), AttributeValue::N(expires_at.to_string()));
+        }
+        item
+    }
+
+    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, MalformedSnapshot> {
+        let s = |name: &'static str| item.get(name).and_then(|v| v.as_s().ok()).cloned().ok_or(MalformedSnapshot(name));
+        let n = |name: &'static str| item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .ok_or(MalformedSnapshot(name));
+        let time = |name: &'static str| item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<i64>().ok())
+            .ok_or(MalformedSnapshot(name));
+
+        Ok(TownSnapshot {
+            town_id: s("town_id")?,
+            timestamp: time("timestamp")?,
+            town_name: s("town_name")?,
+            bank: n("bank")?,
+            upkeep: n("upkeep")?,
+            residents: n("residents")? as usize,
+            area: n("area")?,
+            mayor: s("mayor")?,
+            nation: item.get("nation").and_then(|v| v.as_s().ok()).cloned(),
+            board: s("board")?,
+            // Only points that expire carry the attribute
+            expires_at: match item.get("expires_at") {
+                Some(_) => Some(time("expires_at")?),
+                None => None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stored_rows_and_rejects_malformed_ones() {
+        let mut town = Town::test_town("Rome");
+        town.nation = Some("Roma".to_owned());
+        town.last_updated = 1_733_054_400;
+        let mut snapshot = TownSnapshot::from_town(&town);
+        snapshot.expires_at = Some(1_735_646_400);
+        let item = snapshot.to_dynamodb_item();
+        assert_eq!(TownSnapshot::from_dynamodb_item(&item).unwrap(), snapshot);
+
+        let mut missing = item.clone();
+        missing.remove("bank");
+        assert_eq!(TownSnapshot::from_dynamodb_item(&missing).unwrap_err().0, "bank");
+
+        let mut malformed = item;
+        malformed.insert("timestamp".to_owned(), AttributeValue::S("noon".to_owned()));
+        assert_eq!(TownSnapshot::from_dynamodb_item(&malformed).unwrap_err().0, "timestamp");
+    }
+}
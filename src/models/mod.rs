@@ -1,5 +1,11 @@
 // Filename: models/mod.rs
 // Entry point for models modules
 
+pub mod dynmap;
+pub mod events;
+pub mod guilds;
+pub mod history;
+pub mod nations;
+pub mod players;
 pub mod towns;
-
+pub mod watches;
\ No newline at end of file
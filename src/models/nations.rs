@@ -0,0 +1,156 @@
+// Filename: models/nations.rs
+// Nation aggregated from its member towns during ingestion
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// A stored nation row missing an attribute every row has, or holding it in the wrong form.
+#[derive(Debug, thiserror::Error)]
+#[error("stored nation row has a missing or malformed `{0}` attribute")]
+pub struct MalformedNation(pub &'static str);
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Nation {
+    pub nation_name: String,
+    pub nation_name_lower: String,
+    pub capital: Option<String>,
+    /// Mayor of the capital
+    pub leader: Option<String>,
+    /// Member town names, alphabetically
+    pub towns: Vec<String>,
+    pub residents: usize,
+    /// Combined bank of every member town
+    pub bank: f64,
+    pub area: f64,
+    /// Combined daily upkeep of every member town
+    pub upkeep: f64,
+    /// Unix time of the first upkeep a member town cannot pay
+    pub next_fall: Option<i64>,
+    pub next_falling_town: Option<String>,
+    pub content_hash: String,
+    pub last_updated: i64,
+    /// Unix time the last member town left or fell; the row then holds the final state
+    pub disbanded_at: Option<i64>,
+}
+
+impl Nation {
+    /// Hash of everything aggregated from the towns, ignoring bookkeeping fields.
+    pub fn compute_content_hash(&self) -> String {
+        let mut content = self.clone();
+        content.content_hash = String::new();
+        content.last_updated = 0;
+        content.disbanded_at = None;
+        let bytes = serde_json::to_vec(&content).unwrap_or_default();
+        hex::encode(Sha256::digest(&bytes))
+    }
+
+    pub fn is_disbanded(&self) -> bool {
+        self.disbanded_at.is_some()
+    }
+
+    /// Number of claimed 16x16 chunks across every member town.
+    pub fn chunks(&self) -> u64 {
+        (self.area / 256.0).round() as u64
+    }
+
+    pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
+        let optional_s = |value: &Option<String>| match value {
+            Some(s) => AttributeValue::S(s.clone()),
+            None => AttributeValue::Null(true),
+        };
+        let optional_n = |value: Option<i64>| match value {
+            Some(n) => AttributeValue::N(n.to_string()),
+            None => AttributeValue::Null(true),
+        };
+
+        let mut item = HashMap::new();
+        item.insert("nation_name_lower".to_owned(), AttributeValue::S(self.nation_name_lower.clone()));
+        item.insert("nation_name".to_owned(), AttributeValue::S(self.nation_name.clone()));
+        item.insert("timestamp".to_owned(), AttributeValue::N(self.last_updated.to_string()));
+        item.insert("capital".to_owned(), optional_s(&self.capital));
+        item.insert("leader".to_owned(), optional_s(&self.leader));
+        if !self.towns.is_empty() {
+            item.insert("towns".to_owned(), AttributeValue::Ss(self.towns.clone()));
+        } else {
+            item.insert("towns".to_owned(), AttributeValue::Null(true));
+        }
+        item.insert("residents".to_owned(), AttributeValue::N(self.residents.to_string()));
+        item.insert("bank".to_owned(), AttributeValue::N(self.bank.to_string()));
+        item.insert("area".to_owned(), AttributeValue::N(self.area.to_string()));
+        item.insert("upkeep".to_owned(), AttributeValue::N(self.upkeep.to_string()));
+        item.insert("next_fall".to_owned(), optional_n(self.next_fall));
+        item.insert("next_falling_town".to_owned(), optional_s(&self.next_falling_town));
+        item.insert("content_hash".to_owned(), AttributeValue::S(self.content_hash.clone()));
+        item.insert("disbanded_at".to_owned(), optional_n(self.disbanded_at));
+        item
+    }
+
+    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, MalformedNation> {
+        let s = |name: &'static str| item.get(name).and_then(|v| v.as_s().ok()).cloned().ok_or(MalformedNation(name));
+        let n = |name: &'static str| item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .ok_or(MalformedNation(name));
+        let optional_s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        let optional_n = |name: &str| item.get(name).and_then(|v| v.as_n().ok()).and_then(|n| n.parse::<i64>().ok());
+
+        Ok(Nation {
+            nation_name: s("nation_name")?,
+            nation_name_lower: s("nation_name_lower")?,
+            capital: optional_s("capital"),
+            leader: optional_s("leader"),
+            // Empty sets are stored as null, as DynamoDB has no empty string set
+            towns: item.get("towns")
+                .and_then(|v| v.as_ss().ok())
+                .cloned()
+                .unwrap_or_default(),
+            residents: n("residents")? as usize,
+            bank: n("bank")?,
+            area: n("area")?,
+            upkeep: n("upkeep")?,
+            next_fall: optional_n("next_fall"),
+            next_falling_town: optional_s("next_falling_town"),
+            content_hash: s("content_hash")?,
+            last_updated: optional_n("timestamp").ok_or(MalformedNation("timestamp"))?,
+            disbanded_at: optional_n("disbanded_at"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stored_rows_and_rejects_malformed_ones() {
+        let mut nation = Nation {
+            nation_name: "Roma".to_owned(),
+            nation_name_lower: "roma".to_owned(),
+            capital: Some("Rome".to_owned()),
+            leader: Some("Steve".to_owned()),
+            towns: vec!["Ostia".to_owned(), "Rome".to_owned()],
+            residents: 4,
+            bank: 250.5,
+            area: 1024.0,
+            upkeep: 20.0,
+            next_fall: None,
+            next_falling_town: None,
+            content_hash: String::new(),
+            last_updated: 1_733_054_400,
+            disbanded_at: None,
+        };
+        nation.content_hash = nation.compute_content_hash();
+        let item = nation.to_dynamodb_item();
+        assert_eq!(Nation::from_dynamodb_item(&item).unwrap(), nation);
+
+        let mut missing = item.clone();
+        missing.remove("bank");
+        assert_eq!(Nation::from_dynamodb_item(&missing).unwrap_err().0, "bank");
+
+        let mut malformed = item;
+        malformed.insert("timestamp".to_owned(), AttributeValue::S("yesterday".to_owned()));
+        assert_eq!(Nation::from_dynamodb_item(&malformed).unwrap_err().0, "timestamp");
+    }
+}
@@ -0,0 +1,156 @@
+// Filename: models/players.rs
+// Player index entry built from town residents and trusted players during ingestion
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// A stored player row missing an attribute every row has, or holding it in the wrong form.
+#[derive(Debug, thiserror::Error)]
+#[error("stored player row has a missing or malformed `{0}` attribute")]
+pub struct MalformedPlayer(pub &'static str);
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerRole {
+    /// Mayor of a nation's capital
+    Leader,
+    Mayor,
+    Resident,
+}
+
+impl PlayerRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayerRole::Leader => "leader",
+            PlayerRole::Mayor => "mayor",
+            PlayerRole::Resident => "resident",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "leader" => Some(PlayerRole::Leader),
+            "mayor" => Some(PlayerRole::Mayor),
+            "resident" => Some(PlayerRole::Resident),
+            _ => None,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            PlayerRole::Leader => "Nation Leader",
+            PlayerRole::Mayor => "Mayor",
+            PlayerRole::Resident => "Resident",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Player {
+    pub player_name: String,
+    pub player_name_lower: String,
+    /// Town the player lives in, if any
+    pub town: Option<String>,
+    pub nation: Option<String>,
+    pub role: Option<PlayerRole>,
+    /// Towns that list the player as trusted, alphabetically
+    pub trusted_in: Vec<String>,
+    pub content_hash: String,
+    pub last_updated: i64,
+}
+
+impl Player {
+    /// Hash of everything indexed from the towns, ignoring bookkeeping fields.
+    pub fn compute_content_hash(&self) -> String {
+        let mut content = self.clone();
+        content.content_hash = String::new();
+        content.last_updated = 0;
+        let bytes = serde_json::to_vec(&content).unwrap_or_default();
+        hex::encode(Sha256::digest(&bytes))
+    }
+
+    /// True once the player neither lives in nor is trusted in any town.
+    pub fn is_unaffiliated(&self) -> bool {
+        self.town.is_none() && self.trusted_in.is_empty()
+    }
+
+    pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
+        let optional_s = |value: Option<&str>| match value {
+            Some(s) => AttributeValue::S(s.to_owned()),
+            None => AttributeValue::Null(true),
+        };
+
+        let mut item = HashMap::new();
+        item.insert("player_name_lower".to_owned(), AttributeValue::S(self.player_name_lower.clone()));
+        item.insert("player_name".to_owned(), AttributeValue::S(self.player_name.clone()));
+        item.insert("timestamp".to_owned(), AttributeValue::N(self.last_updated.to_string()));
+        item.insert("town".to_owned(), optional_s(self.town.as_deref()));
+        item.insert("nation".to_owned(), optional_s(self.nation.as_deref()));
+        item.insert("role".to_owned(), optional_s(self.role.map(|role| role.as_str())));
+        if !self.trusted_in.is_empty() {
+            item.insert("trusted_in".to_owned(), AttributeValue::Ss(self.trusted_in.clone()));
+        } else {
+            item.insert("trusted_in".to_owned(), AttributeValue::Null(true));
+        }
+        item.insert("content_hash".to_owned(), AttributeValue::S(self.content_hash.clone()));
+        item
+    }
+
+    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, MalformedPlayer> {
+        let s = |name: &'static str| item.get(name).and_then(|v| v.as_s().ok()).cloned().ok_or(MalformedPlayer(name));
+        let optional_s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        let role = match optional_s("role") {
+            Some(role) => Some(PlayerRole::parse(&role).ok_or(MalformedPlayer("role"))?),
+            None => None,
+        };
+
+        Ok(Player {
+            player_name: s("player_name")?,
+            player_name_lower: s("player_name_lower")?,
+            town: optional_s("town"),
+            nation: optional_s("nation"),
+            role,
+            // Empty sets are stored as null, as DynamoDB has no empty string set
+            trusted_in: item.get("trusted_in")
+                .and_then(|v| v.as_ss().ok())
+                .cloned()
+                .unwrap_or_default(),
+            content_hash: s("content_hash")?,
+            last_updated: item.get("timestamp")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse().ok())
+                .ok_or(MalformedPlayer("timestamp"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stored_rows_and_rejects_malformed_ones() {
+        let mut player = Player {
+            player_name: "Steve".to_owned(),
+            player_name_lower: "steve".to_owned(),
+            town: Some("Rome".to_owned()),
+            nation: None,
+            role: Some(PlayerRole::Mayor),
+            trusted_in: vec!["Ostia".to_owned()],
+            content_hash: String::new(),
+            last_updated: 1_733_054_400,
+        };
+        player.content_hash = player.compute_content_hash();
+        let item = player.to_dynamodb_item();
+        assert_eq!(Player::from_dynamodb_item(&item).unwrap(), player);
+
+        let mut missing = item.clone();
+        missing.remove("player_name_lower");
+        assert_eq!(Player::from_dynamodb_item(&missing).unwrap_err().0, "player_name_lower");
+
+        let mut malformed = item;
+        malformed.insert("role".to_owned(), AttributeValue::S("king".to_owned()));
+        assert_eq!(Player::from_dynamodb_item(&malformed).unwrap_err().0, "role");
+    }
+}
@@ -1,13 +1,23 @@
 use aws_sdk_dynamodb::types::AttributeValue;
 use std::collections::HashMap;
-use aws_sdk_dynamodb::Error;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::services::geometry::BoundingBox;
+
+/// A stored town row missing an attribute every row has, or holding it in the wrong form.
+#[derive(Debug, thiserror::Error)]
+#[error("stored town row has a missing or malformed `{0}` attribute")]
+pub struct MalformedTown(pub &'static str);
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Town {
+    /// Stable internal ID that survives renames, unlike `town_name_lower`
+    pub town_id: String,
     pub town_name: String,
     pub town_name_lower: String,
     pub nation: Option<String>,
+    /// Whether the town is its nation's capital
+    pub capital: bool,
     pub mayor: String,
     pub peaceful: bool,
     pub culture: String,
@@ -20,80 +30,256 @@ pub struct Town {
     pub trusted_players: Vec<String>,
     pub area: f64,
     pub coords: (f64, f64),
+    pub outposts: Vec<(f64, f64)>,
+    pub bounds: (f64, f64, f64, f64),
+    pub content_hash: String,
     pub last_updated: i64,
+    /// Unix time this incarnation of the town was first seen. A town that falls
+    /// and is later founded again under the same name starts a new epoch.
+    pub epoch: i64,
+    /// Unix time the town disappeared from the map; its row then holds the final state
+    pub fallen_at: Option<i64>,
+    /// Name the town continued under, when it vanished because it was renamed
+    pub renamed_to: Option<String>,
+    /// Name of the town it was merged into, when it vanished because of a merge
+    pub merged_into: Option<String>,
+    /// Unix time of the newest history point written for the town
+    pub history_at: Option<i64>,
 }
 
 impl Town {
+    /// Hash of everything ingestion reads from the map, ignoring bookkeeping
+    /// fields, so an unchanged town can be told apart without a read.
+    pub fn compute_content_hash(&self) -> String {
+        let mut content = self.clone();
+        content.content_hash = String::new();
+        content.last_updated = 0;
+        content.epoch = 0;
+        content.fallen_at = None;
+        content.town_id = String::new();
+        content.renamed_to = None;
+        content.merged_into = None;
+        content.history_at = None;
+        let bytes = serde_json::to_vec(&content).unwrap_or_default();
+        hex::encode(Sha256::digest(&bytes))
+    }
+
+    /// ID given to a town first seen under `town_name_lower` at `epoch`.
+    pub fn new_id(town_name_lower: &str, epoch: i64) -> String {
+        format!("{}@{}", town_name_lower, epoch)
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        let (min_x, min_z, max_x, max_z) = self.bounds;
+        BoundingBox { min_x, min_z, max_x, max_z }
+    }
+
+    pub fn is_fallen(&self) -> bool {
+        self.fallen_at.is_some()
+    }
+
+    /// Number of claimed 16x16 chunks, across the main claim and every outpost.
+    pub fn chunks(&self) -> u64 {
+        Self::chunks_in(self.area)
+    }
+
+    /// Number of 16x16 chunks covering `area` square blocks.
+    pub fn chunks_in(area: f64) -> u64 {
+        (area / 256.0).round() as u64
+    }
+
     pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
         let mut item = HashMap::new();
-        item.insert("town_name".to_string(), AttributeValue::S(self.town_name.clone()));
-        item.insert("town_name_lower".to_string(), AttributeValue::S(self.town_name_lower.clone()));
-        item.insert("timestamp".to_string(), AttributeValue::N(self.last_updated.to_string()));
-        item.insert("nation".to_string(), match &self.nation {
+        item.insert("town_id".to_owned(), AttributeValue::S(self.town_id.clone()));
+        item.insert("town_name".to_owned(), AttributeValue::S(self.town_name.clone()));
+        item.insert("town_name_lower".to_owned(), AttributeValue::S(self.town_name_lower.clone()));
+        item.insert("timestamp".to_owned(), AttributeValue::N(self.last_updated.to_string()));
+        item.insert("nation".to_owned(), match &self.nation {
             Some(n) => AttributeValue::S(n.clone()),
             None => AttributeValue::Null(true),
         });
-        item.insert("mayor".to_string(), AttributeValue::S(self.mayor.clone()));
-        item.insert("peaceful".to_string(), AttributeValue::Bool(self.peaceful));
-        item.insert("culture".to_string(), AttributeValue::S(self.culture.clone()));
-        item.insert("board".to_string(), AttributeValue::S(self.board.clone()));
-        item.insert("bank".to_string(), AttributeValue::N(self.bank.to_string()));
-        item.insert("upkeep".to_string(), AttributeValue::N(self.upkeep.to_string()));
-        item.insert("founded".to_string(), AttributeValue::N(self.founded.to_string()));
+        item.insert("capital".to_owned(), AttributeValue::Bool(self.capital));
+        item.insert("mayor".to_owned(), AttributeValue::S(self.mayor.clone()));
+        item.insert("peaceful".to_owned(), AttributeValue::Bool(self.peaceful));
+        item.insert("culture".to_owned(), AttributeValue::S(self.culture.clone()));
+        item.insert("board".to_owned(), AttributeValue::S(self.board.clone()));
+        item.insert("bank".to_owned(), AttributeValue::N(self.bank.to_string()));
+        item.insert("upkeep".to_owned(), AttributeValue::N(self.upkeep.to_string()));
+        item.insert("founded".to_owned(), AttributeValue::N(self.founded.to_string()));
         if !self.resources.is_empty() {
-            item.insert("resources".to_string(), AttributeValue::Ss(self.resources.clone()));
+            item.insert("resources".to_owned(), AttributeValue::Ss(self.resources.clone()));
         } else {
-            item.insert("resources".to_string(), AttributeValue::Null(true));
+            item.insert("resources".to_owned(), AttributeValue::Null(true));
         }
         if !self.residents.is_empty() {
-            item.insert("residents".to_string(), AttributeValue::Ss(self.residents.clone()));
+            item.insert("residents".to_owned(), AttributeValue::Ss(self.residents.clone()));
         } else {
-            item.insert("residents".to_string(), AttributeValue::Null(true));
+            item.insert("residents".to_owned(), AttributeValue::Null(true));
         }
         if !self.trusted_players.is_empty() {
-            item.insert("trusted_players".to_string(), AttributeValue::Ss(self.trusted_players.clone()));
+            item.insert("trusted_players".to_owned(), AttributeValue::Ss(self.trusted_players.clone()));
         } else {
-            item.insert("trusted_players".to_string(), AttributeValue::Null(true));
+            item.insert("trusted_players".to_owned(), AttributeValue::Null(true));
+        }
+        item.insert("area".to_owned(), AttributeValue::N(self.area.to_string()));
+        item.insert("coords".to_owned(), AttributeValue::S(format!("{},{}", self.coords.0, self.coords.1)));
+        item.insert("outposts".to_owned(), AttributeValue::S(
+            self.outposts.iter().map(|(x, z)| format!("{},{}", x, z)).collect::<Vec<_>>().join(";")
+        ));
+        item.insert("content_hash".to_owned(), AttributeValue::S(self.content_hash.clone()));
+        item.insert("bounds".to_owned(), AttributeValue::S(format!("{},{},{},{}", self.bounds.0, self.bounds.1, self.bounds.2, self.bounds.3)));
+        item.insert("epoch".to_owned(), AttributeValue::N(self.epoch.to_string()));
+        item.insert("fallen_at".to_owned(), match self.fallen_at {
+            Some(t) => AttributeValue::N(t.to_string()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("renamed_to".to_owned(), match &self.renamed_to {
+            Some(name) => AttributeValue::S(name.clone()),
+            None => AttributeValue::Null(true),
+        });
+        if let Some(history_at) = self.history_at {
+            item.insert("history_at".to_owned(), AttributeValue::N(history_at.to_string()));
         }
-        item.insert("area".to_string(), AttributeValue::N(self.area.to_string()));
-        item.insert("coords".to_string(), AttributeValue::S(format!("{},{}", self.coords.0, self.coords.1)));
+        item.insert("merged_into".to_owned(), match &self.merged_into {
+            Some(name) => AttributeValue::S(name.clone()),
+            None => AttributeValue::Null(true),
+        });
         item
     }
 
-    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, Error> {
-        let coords_str = item.get("coords").unwrap().as_s().unwrap();
-        let coords: Vec<&str> = coords_str.split(',').collect();
-        
+    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, MalformedTown> {
+        let s = |name: &'static str| item.get(name).and_then(|v| v.as_s().ok()).cloned().ok_or(MalformedTown(name));
+        let n = |name: &'static str| item.get(name)
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse::<f64>().ok())
+            .ok_or(MalformedTown(name));
+        let optional_s = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        let optional_n = |name: &str| item.get(name).and_then(|v| v.as_n().ok()).and_then(|n| n.parse::<i64>().ok());
+        let time = |name: &'static str| optional_n(name).ok_or(MalformedTown(name));
+        // Empty sets are stored as null, as DynamoDB has no empty string set
+        let list = |name: &str| item.get(name)
+            .and_then(|v| v.as_ss().ok())
+            .cloned()
+            .unwrap_or_default();
+
+        let coords = s("coords")?
+            .split_once(',')
+            .and_then(|(x, z)| Some((x.parse().ok()?, z.parse().ok()?)))
+            .ok_or(MalformedTown("coords"))?;
+        // Rows written before bounding boxes were tracked have no `bounds` attribute
+        let bounds: Vec<f64> = optional_s("bounds")
+            .map(|s| s.split(',').filter_map(|n| n.parse().ok()).collect())
+            .unwrap_or_default();
+        let town_name_lower = s("town_name_lower")?;
+        // Rows written before fall detection have neither attribute
+        let epoch = optional_n("epoch").unwrap_or(0);
+        let nation = match item.get("nation") {
+            Some(v) if !v.is_null() => Some(v.as_s().map_err(|_| MalformedTown("nation"))?.clone()),
+            _ => None,
+        };
+        let flag = |name: &'static str| item.get(name).and_then(|v| v.as_bool().ok()).copied();
+
         Ok(Town {
-            town_name: item.get("town_name").unwrap().as_s().unwrap().to_string(),
-            town_name_lower: item.get("town_name_lower").unwrap().as_s().unwrap().to_string(),
-            nation: item.get("nation").and_then(|v| if v.is_null() {
-                None
-            } else {
-                Some(v.as_s().unwrap().to_string())
-            }),
-            mayor: item.get("mayor").unwrap().as_s().unwrap().to_string(),
-            peaceful: *item.get("peaceful").unwrap().as_bool().unwrap(),
-            culture: item.get("culture").unwrap().as_s().unwrap().to_string(),
-            board: item.get("board").unwrap().as_s().unwrap().to_string(),
-            bank: item.get("bank").unwrap().as_n().unwrap().parse().unwrap(),
-            upkeep: item.get("upkeep").unwrap().as_n().unwrap().parse().unwrap(),
-            founded: item.get("founded").unwrap().as_n().unwrap().parse().unwrap(),
-            resources: item.get("resources").map_or(Vec::new(), |v| {
-                v.as_ss().unwrap_or(&Vec::new()).iter().map(|s| s.to_string()).collect()
-            }),
-            residents: item.get("residents").map_or(Vec::new(), |v| {
-                v.as_ss().unwrap_or(&Vec::new()).iter().map(|s| s.to_string()).collect()
-            }),
-            trusted_players: item.get("trusted_players").map_or(Vec::new(), |v| {
-                v.as_ss().unwrap_or(&Vec::new()).iter().map(|s| s.to_string()).collect()
-            }),
-            area: item.get("area").unwrap().as_n().unwrap().parse().unwrap(),
-            coords: (
-                coords[0].parse().unwrap(),
-                coords[1].parse().unwrap()
-            ),
-            last_updated: item.get("timestamp").unwrap().as_n().unwrap().parse().unwrap(),
+            // Rows written before town IDs existed get the ID their first epoch would have had
+            town_id: optional_s("town_id").unwrap_or_else(|| Town::new_id(&town_name_lower, epoch)),
+            town_name: s("town_name")?,
+            town_name_lower,
+            nation,
+            capital: flag("capital").unwrap_or(false),
+            mayor: s("mayor")?,
+            peaceful: flag("peaceful").ok_or(MalformedTown("peaceful"))?,
+            culture: s("culture")?,
+            board: s("board")?,
+            bank: n("bank")?,
+            upkeep: n("upkeep")?,
+            founded: time("founded")?,
+            resources: list("resources"),
+            residents: list("residents"),
+            trusted_players: list("trusted_players"),
+            area: n("area")?,
+            coords,
+            outposts: optional_s("outposts")
+                .map(|s| s.split(';').filter_map(|pair| {
+                    let (x, z) = pair.split_once(',')?;
+                    Some((x.parse().ok()?, z.parse().ok()?))
+                }).collect())
+                .unwrap_or_default(),
+            bounds: match bounds[..] {
+                [min_x, min_z, max_x, max_z] => (min_x, min_z, max_x, max_z),
+                _ => (0.0, 0.0, 0.0, 0.0),
+            },
+            content_hash: optional_s("content_hash").unwrap_or_default(),
+            last_updated: time("timestamp")?,
+            epoch,
+            fallen_at: optional_n("fallen_at"),
+            renamed_to: optional_s("renamed_to"),
+            merged_into: optional_s("merged_into"),
+            history_at: optional_n("history_at"),
         })
     }
 }
+
+#[cfg(test)]
+impl Town {
+    /// A standing one-chunk town with a single resident, for tests.
+    pub fn test_town(name: &str) -> Town {
+        let mut town = Town {
+            town_id: Town::new_id(&name.to_lowercase(), 0),
+            town_name: name.to_owned(),
+            town_name_lower: name.to_lowercase(),
+            nation: None,
+            capital: false,
+            mayor: "Steve".to_owned(),
+            peaceful: false,
+            culture: String::new(),
+            board: String::new(),
+            bank: 0.0,
+            upkeep: 10.0,
+            founded: 0,
+            resources: Vec::new(),
+            residents: vec!["Steve".to_owned()],
+            trusted_players: Vec::new(),
+            area: 256.0,
+            coords: (8.0, 8.0),
+            outposts: Vec::new(),
+            bounds: (0.0, 0.0, 16.0, 16.0),
+            content_hash: String::new(),
+            last_updated: 0,
+            epoch: 0,
+            fallen_at: None,
+            renamed_to: None,
+            merged_into: None,
+            history_at: None,
+        };
+        town.content_hash = town.compute_content_hash();
+        town
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stored_rows_and_rejects_malformed_ones() {
+        let mut town = Town::test_town("Rome");
+        town.nation = Some("Roma".to_owned());
+        town.outposts = vec![(100.0, -50.0)];
+        town.last_updated = 1_733_054_400;
+        town.history_at = Some(1_733_050_000);
+        let item = town.to_dynamodb_item();
+        let read = Town::from_dynamodb_item(&item).unwrap();
+        assert_eq!(read.town_id, town.town_id);
+        assert_eq!(read.nation, town.nation);
+        assert_eq!(read.history_at, town.history_at);
+        assert_eq!((read.coords, read.outposts.clone(), read.last_updated), (town.coords, town.outposts, town.last_updated));
+
+        let mut missing = item.clone();
+        missing.remove("mayor");
+        assert_eq!(Town::from_dynamodb_item(&missing).unwrap_err().0, "mayor");
+
+        let mut malformed = item;
+        malformed.insert("coords".to_owned(), AttributeValue::S("8".to_owned()));
+        assert_eq!(Town::from_dynamodb_item(&malformed).unwrap_err().0, "coords");
+    }
+}
@@ -0,0 +1,192 @@
+// Filename: models/watches.rs
+// Subscription to changes of a town or of every town in a nation
+
+use crate::services::search::normalize;
+use aws_sdk_dynamodb::types::AttributeValue;
+use std::collections::HashMap;
+
+/// A stored watch row missing an attribute every row has, or holding it in the wrong form.
+#[derive(Debug, thiserror::Error)]
+#[error("stored watch row has a missing or malformed `{0}` attribute")]
+pub struct MalformedWatch(pub &'static str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum WatchKind {
+    Town,
+    Nation,
+}
+
+impl WatchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchKind::Town => "town",
+            WatchKind::Nation => "nation",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "town" => Some(WatchKind::Town),
+            "nation" => Some(WatchKind::Nation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    pub kind: WatchKind,
+    /// Town or nation name as it was subscribed to
+    pub name: String,
+    /// Stable ID of the watched town, which follows it through renames; `None` for nations
+    pub town_id: Option<String>,
+    /// User who subscribed, and who is sent direct messages when no channel is set
+    pub user_id: u64,
+    /// Channel notifications are posted in instead of a direct message
+    pub channel_id: Option<u64>,
+    /// Server the watch was set up in, whose colour and locale notifications use
+    pub guild_id: Option<u64>,
+    /// Notify when the bank drops below this balance
+    pub bank_below: Option<f64>,
+    /// Notify when the projected fall comes within this many hours
+    pub falls_within_hours: Option<u32>,
+    /// Notify when residents join or leave
+    pub residents: bool,
+    /// Notify when the mayor changes
+    pub mayor: bool,
+    /// Notify when the town board changes
+    pub board: bool,
+    /// Notify when the town falls, is renamed or merges
+    pub fallen: bool,
+    pub created_at: i64,
+}
+
+impl Watch {
+    /// Partition key shared by every subscription to the same town or nation:
+    /// the town's ID when known, otherwise the normalized name.
+    pub fn target_key(kind: WatchKind, town_id: Option<&str>, name: &str) -> String {
+        match (kind, town_id) {
+            (WatchKind::Town, Some(town_id)) => format!("town:{}", town_id),
+            _ => format!("{}:{}", kind.as_str(), normalize(name)),
+        }
+    }
+
+    /// Sort key telling apart subscribers of the same target. Channel watches
+    /// include the user who set them up, so one user's watch never replaces
+    /// or removes another's.
+    pub fn subscriber_key(user_id: u64, channel_id: Option<u64>) -> String {
+        match channel_id {
+            Some(channel_id) => format!("channel:{}:user:{}", channel_id, user_id),
+            None => format!("user:{}", user_id),
+        }
+    }
+
+    pub fn target(&self) -> String {
+        Self::target_key(self.kind, self.town_id.as_deref(), &self.name)
+    }
+
+    pub fn subscriber(&self) -> String {
+        Self::subscriber_key(self.user_id, self.channel_id)
+    }
+
+    pub fn to_dynamodb_item(&self) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+        item.insert("target".to_owned(), AttributeValue::S(self.target()));
+        item.insert("subscriber".to_owned(), AttributeValue::S(self.subscriber()));
+        item.insert("kind".to_owned(), AttributeValue::S(self.kind.as_str().to_owned()));
+        item.insert("name".to_owned(), AttributeValue::S(self.name.clone()));
+        item.insert("town_id".to_owned(), match &self.town_id {
+            Some(town_id) => AttributeValue::S(town_id.clone()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("user_id".to_owned(), AttributeValue::N(self.user_id.to_string()));
+        item.insert("channel_id".to_owned(), match self.channel_id {
+            Some(channel_id) => AttributeValue::N(channel_id.to_string()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("guild_id".to_owned(), match self.guild_id {
+            Some(guild_id) => AttributeValue::N(guild_id.to_string()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("bank_below".to_owned(), match self.bank_below {
+            Some(bank) => AttributeValue::N(bank.to_string()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("falls_within_hours".to_owned(), match self.falls_within_hours {
+            Some(hours) => AttributeValue::N(hours.to_string()),
+            None => AttributeValue::Null(true),
+        });
+        item.insert("residents".to_owned(), AttributeValue::Bool(self.residents));
+        item.insert("mayor".to_owned(), AttributeValue::Bool(self.mayor));
+        item.insert("board".to_owned(), AttributeValue::Bool(self.board));
+        item.insert("fallen".to_owned(), AttributeValue::Bool(self.fallen));
+        item.insert("created_at".to_owned(), AttributeValue::N(self.created_at.to_string()));
+        item
+    }
+
+    pub fn from_dynamodb_item(item: &HashMap<String, AttributeValue>) -> Result<Self, MalformedWatch> {
+        let s = |name: &'static str| item.get(name).and_then(|v| v.as_s().ok()).cloned().ok_or(MalformedWatch(name));
+        let n = |name: &'static str| item.get(name).and_then(|v| v.as_n().ok()).ok_or(MalformedWatch(name));
+        let id = |name: &'static str| n(name)?.parse::<u64>().map_err(|_| MalformedWatch(name));
+        let flag = |name: &'static str| item.get(name).and_then(|v| v.as_bool().ok()).copied().ok_or(MalformedWatch(name));
+        // Absent values are stored as null
+        let is_set = |name: &str| item.get(name).is_some_and(|v| !v.is_null());
+
+        Ok(Watch {
+            kind: WatchKind::parse(&s("kind")?).ok_or(MalformedWatch("kind"))?,
+            name: s("name")?,
+            town_id: if is_set("town_id") { Some(s("town_id")?) } else { None },
+            user_id: id("user_id")?,
+            channel_id: if is_set("channel_id") { Some(id("channel_id")?) } else { None },
+            // Watches set up before servers were recorded have no `guild_id`
+            guild_id: if is_set("guild_id") { Some(id("guild_id")?) } else { None },
+            bank_below: match is_set("bank_below") {
+                true => Some(n("bank_below")?.parse().map_err(|_| MalformedWatch("bank_below"))?),
+                false => None,
+            },
+            falls_within_hours: match is_set("falls_within_hours") {
+                true => Some(n("falls_within_hours")?.parse().map_err(|_| MalformedWatch("falls_within_hours"))?),
+                false => None,
+            },
+            residents: flag("residents")?,
+            mayor: flag("mayor")?,
+            board: flag("board")?,
+            fallen: flag("fallen")?,
+            created_at: n("created_at")?.parse().map_err(|_| MalformedWatch("created_at"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stored_rows_and_rejects_malformed_ones() {
+        let watch = Watch {
+            kind: WatchKind::Town,
+            name: "Astarte".to_owned(),
+            town_id: Some("astarte@100".to_owned()),
+            user_id: 1,
+            channel_id: None,
+            guild_id: Some(42),
+            bank_below: Some(100.0),
+            falls_within_hours: None,
+            residents: true,
+            mayor: false,
+            board: false,
+            fallen: true,
+            created_at: 100,
+        };
+        let item = watch.to_dynamodb_item();
+        assert_eq!(Watch::from_dynamodb_item(&item).unwrap(), watch);
+
+        let mut missing = item.clone();
+        missing.remove("user_id");
+        assert_eq!(Watch::from_dynamodb_item(&missing).unwrap_err().0, "user_id");
+
+        let mut malformed = item;
+        malformed.insert("kind".to_owned(), AttributeValue::S("village".to_owned()));
+        assert_eq!(Watch::from_dynamodb_item(&malformed).unwrap_err().0, "kind");
+    }
+}
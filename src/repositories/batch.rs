@@ -0,0 +1,110 @@
+// Filename: repositories/batch.rs
+// Shared BatchWriteItem helper with retries and bounded concurrency
+
+use aws_sdk_dynamodb::types::{AttributeValue, PutRequest, WriteRequest};
+use aws_sdk_dynamodb::Client;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration};
+
+/// DynamoDB accepts at most 25 requests per BatchWriteItem call.
+const BATCH_SIZE: usize = 25;
+
+/// Number of batches in flight at once.
+const MAX_CONCURRENT_BATCHES: usize = 8;
+
+/// Attempts to flush unprocessed items before a batch is given up on.
+const MAX_RETRIES: u32 = 6;
+
+/// Delay before the first retry; doubled on every further attempt.
+const BASE_BACKOFF: Duration = Duration::from_millis(50);
+
+#[derive(Debug, thiserror::Error)]
+pub enum BatchWriteError {
+    #[error(transparent)]
+    Dynamo(Box<aws_sdk_dynamodb::Error>),
+    #[error("{0} items were still unprocessed after {MAX_RETRIES} retries")]
+    Unprocessed(usize),
+    #[error("batch write task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+impl From<aws_sdk_dynamodb::Error> for BatchWriteError {
+    fn from(error: aws_sdk_dynamodb::Error) -> Self {
+        BatchWriteError::Dynamo(Box::new(error))
+    }
+}
+
+/// Puts every item into `table_name`, 25 per request and up to
+/// `MAX_CONCURRENT_BATCHES` requests at a time.
+pub async fn put_items(
+    db_client: &Client,
+    table_name: &str,
+    items: Vec<HashMap<String, AttributeValue>>,
+) -> Result<(), BatchWriteError> {
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_BATCHES));
+    let mut tasks = JoinSet::new();
+    let mut items = items.into_iter().peekable();
+
+    while items.peek().is_some() {
+        let requests = items
+            .by_ref()
+            .take(BATCH_SIZE)
+            .map(|item| {
+                let put = PutRequest::builder()
+                    .set_item(Some(item))
+                    .build()
+                    .expect("PutRequest always has an item");
+                WriteRequest::builder().put_request(put).build()
+            })
+            .collect();
+        let permit = semaphore.clone().acquire_owned().await.expect("Semaphore is never closed");
+        let db_client = db_client.clone();
+        let table_name = table_name.to_owned();
+        tasks.spawn(async move {
+            let result = write_batch(&db_client, &table_name, requests).await;
+            drop(permit);
+            result
+        });
+    }
+
+    let mut first_error = None;
+    while let Some(result) = tasks.join_next().await {
+        if let Err(e) = result.map_err(BatchWriteError::from).and_then(|r| r) {
+            first_error.get_or_insert(e);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Sends one batch, re-sending unprocessed items with exponential backoff.
+async fn write_batch(
+    db_client: &Client,
+    table_name: &str,
+    mut requests: Vec<WriteRequest>,
+) -> Result<(), BatchWriteError> {
+    let mut attempt = 0;
+    loop {
+        let output = db_client
+            .batch_write_item()
+            .request_items(table_name, requests)
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+
+        requests = output
+            .unprocessed_items
+            .and_then(|mut unprocessed| unprocessed.remove(table_name))
+            .unwrap_or_default();
+        if requests.is_empty() {
+            return Ok(());
+        }
+        if attempt == MAX_RETRIES {
+            return Err(BatchWriteError::Unprocessed(requests.len()));
+        }
+        sleep(BASE_BACKOFF * 2u32.pow(attempt)).await;
+        attempt += 1;
+    }
+}
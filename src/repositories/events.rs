@@ -0,0 +1,27 @@
+// Filename: repositories/events.rs
+// Storage for town rename and merge events
+
+use aws_sdk_dynamodb::Client;
+use crate::config::secret::SourceConfig;
+use crate::models::events::TownEvent;
+use crate::repositories::batch::{self, BatchWriteError};
+
+#[derive(Debug)]
+pub struct TownEventRepository<'a> {
+    db_client: &'a Client,
+    table_name: String,
+}
+
+impl<'a> TownEventRepository<'a> {
+    pub fn new(db_client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            db_client,
+            table_name: source.table("town_events"),
+        }
+    }
+
+    pub async fn save_events(&self, events: &[TownEvent]) -> Result<(), BatchWriteError> {
+        let items = events.iter().map(TownEvent::to_dynamodb_item).collect();
+        batch::put_items(self.db_client, &self.table_name, items).await
+    }
+}
@@ -0,0 +1,83 @@
+// Filename: repositories/guilds.rs
+// Storage for per-server settings, one row per guild
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use crate::models::guilds::GuildSettings;
+
+/// Settings apply to a server whichever source it reads, so unlike the map
+/// data this table is not namespaced per source.
+const TABLE_NAME: &str = "guild_settings";
+
+#[derive(Debug)]
+pub struct GuildSettingsRepository<'a> {
+    db_client: &'a Client,
+    table_name: String,
+}
+
+impl<'a> GuildSettingsRepository<'a> {
+    pub fn new(db_client: &'a Client) -> Self {
+        Self {
+            db_client,
+            table_name: TABLE_NAME.to_owned(),
+        }
+    }
+
+    pub async fn save_settings(&self, settings: &GuildSettings) -> Result<(), Error> {
+        self.db_client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(settings.to_dynamodb_item()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the settings of every server that has stored any. Rows that cannot be read are logged and skipped.
+    pub async fn all_settings(&self) -> Result<Vec<GuildSettings>, Error> {
+        let mut settings = Vec::new();
+        let mut start_key = None;
+        loop {
+            let result = self.db_client
+                .scan()
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+
+            for item in result.items() {
+                match GuildSettings::from_dynamodb_item(item) {
+                    Ok(guild) => settings.push(guild),
+                    Err(e) => println!("Skipping unreadable settings row in {}: {}", self.table_name, e),
+                }
+            }
+
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn get_settings(&self, guild_id: u64) -> Result<Option<GuildSettings>, Error> {
+        let result = self.db_client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("guild_id", AttributeValue::N(guild_id.to_string()))
+            .send()
+            .await?;
+
+        match result.item {
+            Some(item) => match GuildSettings::from_dynamodb_item(&item) {
+                Ok(settings) => Ok(Some(settings)),
+                Err(e) => {
+                    println!("Skipping unreadable settings of server {} in {}: {}", guild_id, self.table_name, e);
+                    Ok(None)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Filename: repositories/history.rs
+// Storage for town history, partitioned by town ID and sorted by timestamp
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use crate::config::secret::SourceConfig;
+use crate::models::history::TownSnapshot;
+use crate::repositories::batch::{self, BatchWriteError};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Number of per-town queries in flight at once.
+const MAX_CONCURRENT_QUERIES: usize = 8;
+
+#[derive(Debug)]
+pub struct TownHistoryRepository<'a> {
+    db_client: &'a Client,
+    table_name: String,
+}
+
+impl<'a> TownHistoryRepository<'a> {
+    pub fn new(db_client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            db_client,
+            table_name: source.table("town_history"),
+        }
+    }
+
+    pub async fn save_snapshots(&self, snapshots: &[TownSnapshot]) -> Result<(), BatchWriteError> {
+        let items = snapshots.iter().map(TownSnapshot::to_dynamodb_item).collect();
+        batch::put_items(self.db_client, &self.table_name, items).await
+    }
+
+    /// Returns every point of `town_id` from `from` to `to` inclusive, oldest first.
+    /// Points past their expiry are skipped, since DynamoDB deletes them lazily,
+    /// as are points that cannot be read.
+    pub async fn get_history(&self, town_id: &str, from: i64, to: i64) -> Result<Vec<TownSnapshot>, Error> {
+        let now = chrono::Utc::now().timestamp();
+        let mut snapshots = Vec::new();
+        let mut start_key = None;
+        loop {
+            let result = self.db_client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("town_id = :town_id AND #ts BETWEEN :from AND :to")
+                .expression_attribute_names("#ts", "timestamp")
+                .expression_attribute_values(":town_id", AttributeValue::S(town_id.to_owned()))
+                .expression_attribute_values(":from", AttributeValue::N(from.to_string()))
+                .expression_attribute_values(":to", AttributeValue::N(to.to_string()))
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+
+            for item in result.items() {
+                match TownSnapshot::from_dynamodb_item(item) {
+                    Ok(snapshot) => {
+                        if snapshot.expires_at.is_none_or(|expires_at| expires_at > now) {
+                            snapshots.push(snapshot);
+                        }
+                    }
+                    Err(e) => println!("Skipping unreadable point of town {} in {}: {}", town_id, self.table_name, e),
+                }
+            }
+
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// Returns the newest point of every town in `town_ids` at or before `at`,
+    /// which is the town's state at that time. Towns without one are left out.
+    pub async fn points_at(&self, town_ids: Vec<String>, at: i64) -> Result<Vec<TownSnapshot>, Error> {
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_QUERIES));
+        let mut tasks = JoinSet::new();
+        for town_id in town_ids {
+            let permit = semaphore.clone().acquire_owned().await.expect("Semaphore is never closed");
+            let db_client = self.db_client.clone();
+            let table_name = self.table_name.clone();
+            tasks.spawn(async move {
+                let result = point_at(&db_client, &table_name, &town_id, at).await;
+                drop(permit);
+                result
+            });
+        }
+
+        let mut snapshots = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            if let Some(snapshot) = result.expect("History query task panicked")? {
+                snapshots.push(snapshot);
+            }
+        }
+        Ok(snapshots)
+    }
+}
+
+/// Newest point of `town_id` at or before `at`.
+async fn point_at(db_client: &Client, table_name: &str, town_id: &str, at: i64) -> Result<Option<TownSnapshot>, Error> {
+    let result = db_client
+        .query()
+        .table_name(table_name)
+        .key_condition_expression("town_id = :town_id AND #ts <= :at")
+        .expression_attribute_names("#ts", "timestamp")
+        .expression_attribute_values(":town_id", AttributeValue::S(town_id.to_owned()))
+        .expression_attribute_values(":at", AttributeValue::N(at.to_string()))
+        .scan_index_forward(false)
+        .limit(1)
+        .send()
+        .await?;
+    match result.items().first().map(TownSnapshot::from_dynamodb_item) {
+        Some(Ok(snapshot)) => Ok(Some(snapshot)),
+        Some(Err(e)) => {
+            println!("Skipping unreadable point of town {} in {}: {}", town_id, table_name, e);
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
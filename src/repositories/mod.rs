@@ -1,4 +1,12 @@
 // Filename: repositories/mod.rs
 // Entry point for repositories modules
+pub mod batch;
+pub mod events;
+pub mod guilds;
+pub mod history;
+pub mod nations;
+pub mod players;
 pub mod towns;
 
+
+pub mod watches;
\ No newline at end of file
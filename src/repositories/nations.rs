@@ -0,0 +1,76 @@
+// Filename: repositories/nations.rs
+// Storage for nations, one row per nation holding its latest aggregate
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use crate::config::secret::SourceConfig;
+use crate::models::nations::Nation;
+use crate::repositories::batch::{self, BatchWriteError};
+
+#[derive(Debug)]
+pub struct NationRepository<'a> {
+    db_client: &'a Client,
+    table_name: String,
+}
+
+impl<'a> NationRepository<'a> {
+    pub fn new(db_client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            db_client,
+            table_name: source.table("nations"),
+        }
+    }
+
+    pub async fn save_nations(&self, nations: &[Nation]) -> Result<(), BatchWriteError> {
+        let items = nations.iter().map(Nation::to_dynamodb_item).collect();
+        batch::put_items(self.db_client, &self.table_name, items).await
+    }
+
+    /// Returns every stored nation, including disbanded ones. Rows that cannot be read are logged and skipped.
+    pub async fn all_nations(&self) -> Result<Vec<Nation>, Error> {
+        let mut nations = Vec::new();
+        let mut start_key = None;
+        loop {
+            let result = self.db_client
+                .scan()
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+
+            for item in result.items() {
+                match Nation::from_dynamodb_item(item) {
+                    Ok(nation) => nations.push(nation),
+                    Err(e) => println!("Skipping unreadable nation row in {}: {}", self.table_name, e),
+                }
+            }
+
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(nations)
+    }
+
+    pub async fn get_nation(&self, nation_name: &str) -> Result<Option<Nation>, Error> {
+        let result = self.db_client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("nation_name_lower", AttributeValue::S(nation_name.to_lowercase()))
+            .send()
+            .await?;
+
+        match result.item {
+            Some(item) => match Nation::from_dynamodb_item(&item) {
+                Ok(nation) => Ok(Some(nation)),
+                Err(e) => {
+                    println!("Skipping unreadable row of nation {} in {}: {}", nation_name, self.table_name, e);
+                    Ok(None)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Filename: repositories/players.rs
+// Storage for the player index, one row per player
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use crate::config::secret::SourceConfig;
+use crate::models::players::Player;
+use crate::repositories::batch::{self, BatchWriteError};
+
+#[derive(Debug)]
+pub struct PlayerRepository<'a> {
+    db_client: &'a Client,
+    table_name: String,
+}
+
+impl<'a> PlayerRepository<'a> {
+    pub fn new(db_client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            db_client,
+            table_name: source.table("players"),
+        }
+    }
+
+    pub async fn save_players(&self, players: &[Player]) -> Result<(), BatchWriteError> {
+        let items = players.iter().map(Player::to_dynamodb_item).collect();
+        batch::put_items(self.db_client, &self.table_name, items).await
+    }
+
+    /// Returns every indexed player, including those no longer in any town. Rows that cannot be read are logged and skipped.
+    pub async fn all_players(&self) -> Result<Vec<Player>, Error> {
+        let mut players = Vec::new();
+        let mut start_key = None;
+        loop {
+            let result = self.db_client
+                .scan()
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+
+            for item in result.items() {
+                match Player::from_dynamodb_item(item) {
+                    Ok(player) => players.push(player),
+                    Err(e) => println!("Skipping unreadable player row in {}: {}", self.table_name, e),
+                }
+            }
+
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(players)
+    }
+
+    pub async fn get_player(&self, player_name: &str) -> Result<Option<Player>, Error> {
+        let result = self.db_client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("player_name_lower", AttributeValue::S(player_name.to_lowercase()))
+            .send()
+            .await?;
+
+        match result.item {
+            Some(item) => match Player::from_dynamodb_item(&item) {
+                Ok(player) => Ok(Some(player)),
+                Err(e) => {
+                    println!("Skipping unreadable row of player {} in {}: {}", player_name, self.table_name, e);
+                    Ok(None)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+}
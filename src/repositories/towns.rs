@@ -1,49 +1,147 @@
 use aws_sdk_dynamodb::Client;
 use aws_sdk_dynamodb::Error;
 use aws_sdk_dynamodb::types::AttributeValue;
-use crate::models::towns::Town;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::config::secret::SourceConfig;
+use crate::repositories::batch::{self, BatchWriteError};
+use crate::models::towns::{MalformedTown, Town};
+
+/// Global secondary index over every row, partitioned by `town_name_lower` and sorted by `timestamp`.
+const NAME_INDEX: &str = "town_name_lower-index";
+
+/// Name index queries in flight at once while loading every town.
+const MAX_CONCURRENT_QUERIES: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TownReadError {
+    #[error(transparent)]
+    Dynamo(Box<Error>),
+    #[error(transparent)]
+    Malformed(#[from] MalformedTown),
+}
+
+impl From<Error> for TownReadError {
+    fn from(error: Error) -> Self {
+        TownReadError::Dynamo(Box::new(error))
+    }
+}
 
 #[derive(Debug)]
 pub struct TownRepository<'a> {
     db_client: &'a Client,
+    table_name: String,
 }
 
 impl<'a> TownRepository<'a> {
-    pub fn new(db_client: &'a Client) -> Self {
-        Self { db_client }
+    pub fn new(db_client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            db_client,
+            table_name: source.table("towns"),
+        }
+    }
+
+    /// Writes every town with batched, concurrent BatchWriteItem calls.
+    pub async fn save_towns(&self, towns: &[Town]) -> Result<(), BatchWriteError> {
+        let items = towns.iter().map(Town::to_dynamodb_item).collect();
+        batch::put_items(self.db_client, &self.table_name, items).await
+    }
+
+    pub async fn get_town(&self, town_name: &str) -> Result<Option<Town>, TownReadError> {
+        match self.newest_row(&town_name.to_lowercase(), None).await? {
+            Some(item) => Ok(Some(Town::from_dynamodb_item(&item)?)),
+            None => Ok(None),
+        }
     }
 
-    pub async fn save_town(&self, town: &Town) -> Result<(), Error> {
-        let item = town.to_dynamodb_item();
-        self.db_client
-            .put_item()
-            .table_name("towns")
-            .set_item(Some(item))
-            .condition_expression("attribute_not_exists(id)")
-            .send()
-            .await?;
-        Ok(())
+    /// Returns the newest row of every town, keyed by `town_name_lower`,
+    /// including towns whose newest row records their fall.
+    pub async fn latest_towns(&self) -> Result<HashMap<String, Town>, Error> {
+        self.newest_rows(None).await
     }
 
-    pub async fn get_town(&self, town_name: &str) -> Result<Option<Town>, Error> {
-        let town_name_lower = town_name.to_lowercase();
-        let result = self.db_client
+    /// Returns every town as it was stored at `at`: the newest row of each
+    /// town written no later than `at`, keyed by `town_name_lower`.
+    pub async fn towns_at(&self, at: i64) -> Result<HashMap<String, Town>, Error> {
+        self.newest_rows(Some(at)).await
+    }
+
+    /// Looks up the newest row of every town with one name index query each,
+    /// a few at a time. Rows that cannot be read are logged and skipped.
+    async fn newest_rows(&self, until: Option<i64>) -> Result<HashMap<String, Town>, Error> {
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_QUERIES));
+        let mut tasks = JoinSet::new();
+        for name in self.town_names().await? {
+            let permit = semaphore.clone().acquire_owned().await.expect("Semaphore is never closed");
+            let db_client = self.db_client.clone();
+            let table_name = self.table_name.clone();
+            tasks.spawn(async move {
+                let repository = TownRepository { db_client: &db_client, table_name };
+                let result = repository.newest_row(&name, until).await;
+                drop(permit);
+                result.map(|item| (name, item))
+            });
+        }
+
+        let mut newest = HashMap::new();
+        while let Some(result) = tasks.join_next().await {
+            let (name, item) = result.expect("Town queries do not panic")?;
+            let Some(item) = item else {
+                continue;
+            };
+            match Town::from_dynamodb_item(&item) {
+                Ok(town) => {
+                    newest.insert(name, town);
+                }
+                Err(e) => println!("Skipping unreadable row of town {} in {}: {}", name, self.table_name, e),
+            }
+        }
+        Ok(newest)
+    }
+
+    /// Newest row stored under `town_name_lower`, no later than `until` if given.
+    async fn newest_row(&self, town_name_lower: &str, until: Option<i64>) -> Result<Option<HashMap<String, AttributeValue>>, Error> {
+        let mut query = self.db_client
             .query()
-            .table_name("towns") // Use the correct table name
-            .index_name("town_name_lower-index") // Use the secondary index
-            .key_condition_expression("town_name_lower = :town_name_lower")
-            .expression_attribute_values(":town_name_lower", AttributeValue::S(town_name_lower))
+            .table_name(&self.table_name)
+            .index_name(NAME_INDEX)
+            .expression_attribute_values(":town_name_lower", AttributeValue::S(town_name_lower.to_owned()))
             .limit(1)
-            .scan_index_forward(false)
-            .send()
-            .await?;
-
-        if let Some(items) = result.items {
-            if let Some(item) = items.first() {
-                // Convert DynamoDB item to Town struct
-                return Ok(Some(Town::from_dynamodb_item(item)?));
+            .scan_index_forward(false);
+        query = match until {
+            Some(until) => query
+                .key_condition_expression("town_name_lower = :town_name_lower AND #timestamp <= :until")
+                .expression_attribute_names("#timestamp", "timestamp")
+                .expression_attribute_values(":until", AttributeValue::N(until.to_string())),
+            None => query.key_condition_expression("town_name_lower = :town_name_lower"),
+        };
+        let result = query.send().await?;
+        Ok(result.items.and_then(|items| items.into_iter().next()))
+    }
+
+    /// Every town name ever stored, reading nothing but the name off the name index.
+    async fn town_names(&self) -> Result<HashSet<String>, Error> {
+        let mut names = HashSet::new();
+        let mut start_key = None;
+        loop {
+            let result = self.db_client
+                .scan()
+                .table_name(&self.table_name)
+                .index_name(NAME_INDEX)
+                .projection_expression("town_name_lower")
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+
+            names.extend(result.items().iter().filter_map(|item| item.get("town_name_lower")?.as_s().ok().cloned()));
+
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
             }
         }
-        Ok(None)
-    } 
+        Ok(names)
+    }
 }
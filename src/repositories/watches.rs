@@ -0,0 +1,74 @@
+// Filename: repositories/watches.rs
+// Storage for watch subscriptions, partitioned by target and sorted by subscriber
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use aws_sdk_dynamodb::types::AttributeValue;
+use crate::config::secret::SourceConfig;
+use crate::models::watches::Watch;
+
+#[derive(Debug)]
+pub struct WatchRepository<'a> {
+    db_client: &'a Client,
+    table_name: String,
+}
+
+impl<'a> WatchRepository<'a> {
+    pub fn new(db_client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            db_client,
+            table_name: source.table("watches"),
+        }
+    }
+
+    /// Stores `watch`, replacing the subscriber's earlier watch of the same target.
+    pub async fn save_watch(&self, watch: &Watch) -> Result<(), Error> {
+        self.db_client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(watch.to_dynamodb_item()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the subscriber's watch of the target. Returns whether one existed.
+    pub async fn delete_watch(&self, target: &str, subscriber: &str) -> Result<bool, Error> {
+        let result = self.db_client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("target", AttributeValue::S(target.to_owned()))
+            .key("subscriber", AttributeValue::S(subscriber.to_owned()))
+            .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
+            .send()
+            .await?;
+        Ok(result.attributes.is_some())
+    }
+
+    /// Returns every watch of every target. Rows that cannot be read are logged and skipped.
+    pub async fn all_watches(&self) -> Result<Vec<Watch>, Error> {
+        let mut watches = Vec::new();
+        let mut start_key = None;
+        loop {
+            let result = self.db_client
+                .scan()
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await?;
+
+            for item in result.items() {
+                match Watch::from_dynamodb_item(item) {
+                    Ok(watch) => watches.push(watch),
+                    Err(e) => println!("Skipping unreadable watch in {}: {}", self.table_name, e),
+                }
+            }
+
+            start_key = result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(watches)
+    }
+}
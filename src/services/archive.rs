@@ -0,0 +1,266 @@
+// Filename: services/archive.rs
+// Compressed archive of every raw marker file fetched, on local disk or in S3
+
+use crate::config::db_client::create_s3_client;
+use crate::config::secret::{ArchiveBackend, ArchiveConfig};
+use aws_sdk_s3::primitives::ByteStream;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type ArchiveResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// File extension of every snapshot; the stem is the fetch time in Unix seconds.
+pub const SNAPSHOT_EXTENSION: &str = ".json.gz";
+
+/// Expired snapshots are looked for at most this often per source.
+const PRUNE_INTERVAL_SECS: i64 = 86_400;
+
+/// What the archive remembers about one source between fetches.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveState {
+    /// Hash of the newest stored snapshot, to skip identical ones
+    pub last_hash: Option<String>,
+    /// When expired snapshots were last deleted
+    pub last_pruned: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+enum SnapshotStore {
+    Local(PathBuf),
+    S3 { client: aws_sdk_s3::Client, bucket: String },
+}
+
+/// Stores snapshots as `<source>/<unix seconds>.json.gz`.
+#[derive(Debug, Clone)]
+pub struct SnapshotArchive {
+    store: SnapshotStore,
+    retention_secs: Option<u64>,
+}
+
+impl SnapshotArchive {
+    pub async fn from_config(config: &ArchiveConfig) -> Self {
+        let store = match &config.backend {
+            ArchiveBackend::Local { path } => SnapshotStore::Local(PathBuf::from(path)),
+            ArchiveBackend::S3 { bucket } => SnapshotStore::S3 {
+                client: create_s3_client().await,
+                bucket: bucket.clone(),
+            },
+        };
+        Self {
+            store,
+            retention_secs: config.retention_days.map(|days| days * 86_400),
+        }
+    }
+
+    /// Picks up where an earlier run left off for `source`, hashing its newest
+    /// stored snapshot so an unchanged map is not archived again after a restart.
+    pub async fn resume(&self, source: &str) -> ArchiveResult<ArchiveState> {
+        let Some((_, key)) = self.list(source).await?.into_iter().max() else {
+            return Ok(ArchiveState::default());
+        };
+        let mut raw = Vec::new();
+        GzDecoder::new(self.read(&key).await?.as_slice()).read_to_end(&mut raw)?;
+        Ok(ArchiveState {
+            last_hash: Some(hash(&raw)),
+            last_pruned: None,
+        })
+    }
+
+    /// Archives `raw` for `source` unless it is identical to the previous snapshot,
+    /// then prunes snapshots past the retention period if that has not been done
+    /// for a day. `state` is updated with what was stored and pruned.
+    ///
+    /// Returns whether a new snapshot was written.
+    pub async fn store(&self, source: &str, fetched_at: i64, raw: &[u8], state: &mut ArchiveState) -> ArchiveResult<bool> {
+        let hash = hash(raw);
+        if state.last_hash.as_deref() == Some(hash.as_str()) {
+            return Ok(false);
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw)?;
+        let compressed = encoder.finish()?;
+        let key = format!("{}/{}{}", source, fetched_at, SNAPSHOT_EXTENSION);
+
+        match &self.store {
+            SnapshotStore::Local(root) => {
+                let path = root.join(&key);
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(path, compressed).await?;
+            }
+            SnapshotStore::S3 { client, bucket } => {
+                client
+                    .put_object()
+                    .bucket(bucket)
+                    .key(&key)
+                    .content_type("application/json")
+                    .content_encoding("gzip")
+                    .body(ByteStream::from(compressed))
+                    .send()
+                    .await?;
+            }
+        }
+        state.last_hash = Some(hash);
+
+        if let Some(retention) = self.retention_secs {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            if state.last_pruned.is_none_or(|at| now - at >= PRUNE_INTERVAL_SECS) {
+                self.prune(source, now - retention as i64).await?;
+                state.last_pruned = Some(now);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Deletes every snapshot of `source` fetched before `cutoff`.
+    async fn prune(&self, source: &str, cutoff: i64) -> ArchiveResult<()> {
+        for (time, key) in self.list(source).await? {
+            if time < cutoff {
+                self.delete(&key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every snapshot of `source` as its fetch time and key, in no particular order.
+    async fn list(&self, source: &str) -> ArchiveResult<Vec<(i64, String)>> {
+        let prefix = format!("{}/", source);
+        let mut snapshots = Vec::new();
+        match &self.store {
+            SnapshotStore::Local(root) => {
+                let mut entries = match tokio::fs::read_dir(root.join(source)).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(snapshots),
+                    Err(e) => return Err(e.into()),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if let Some(time) = snapshot_time(&name) {
+                        snapshots.push((time, format!("{}{}", prefix, name)));
+                    }
+                }
+            }
+            SnapshotStore::S3 { client, bucket } => {
+                let mut token = None;
+                loop {
+                    let page = client
+                        .list_objects_v2()
+                        .bucket(bucket)
+                        .prefix(&prefix)
+                        .set_continuation_token(token)
+                        .send()
+                        .await?;
+                    for key in page.contents().iter().filter_map(|object| object.key()) {
+                        if let Some(time) = snapshot_time(key.trim_start_matches(&prefix)) {
+                            snapshots.push((time, key.to_owned()));
+                        }
+                    }
+                    token = page.next_continuation_token;
+                    if token.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// Reads the compressed snapshot stored under `key`.
+    async fn read(&self, key: &str) -> ArchiveResult<Vec<u8>> {
+        match &self.store {
+            SnapshotStore::Local(root) => Ok(tokio::fs::read(root.join(key)).await?),
+            SnapshotStore::S3 { client, bucket } => {
+                let object = client.get_object().bucket(bucket).key(key).send().await?;
+                Ok(object.body.collect().await?.into_bytes().to_vec())
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> ArchiveResult<()> {
+        match &self.store {
+            SnapshotStore::Local(root) => tokio::fs::remove_file(root.join(key)).await?,
+            SnapshotStore::S3 { client, bucket } => {
+                client.delete_object().bucket(bucket).key(key).send().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hash(raw: &[u8]) -> String {
+    hex::encode(Sha256::digest(raw))
+}
+
+/// Reads the fetch time out of a snapshot file name such as `1733079600.json.gz`.
+pub fn snapshot_time(name: &str) -> Option<i64> {
+    name.strip_suffix(SNAPSHOT_EXTENSION)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::secret::ArchiveConfig;
+
+    async fn local_archive(name: &str, retention_days: Option<u64>) -> (SnapshotArchive, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("ccnetizen-archive-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = ArchiveConfig {
+            backend: ArchiveBackend::Local { path: dir.to_string_lossy().into_owned() },
+            retention_days,
+        };
+        (SnapshotArchive::from_config(&config).await, dir)
+    }
+
+    fn stored_times(dir: &std::path::Path) -> Vec<i64> {
+        let mut times: Vec<i64> = std::fs::read_dir(dir.join("earth"))
+            .unwrap()
+            .filter_map(|entry| snapshot_time(&entry.unwrap().file_name().to_string_lossy()))
+            .collect();
+        times.sort_unstable();
+        times
+    }
+
+    #[tokio::test]
+    async fn skips_identical_snapshots_across_restarts() {
+        let (archive, dir) = local_archive("dedup", None).await;
+        let mut state = archive.resume("earth").await.unwrap();
+        assert_eq!(state.last_hash, None);
+
+        assert!(archive.store("earth", 100, b"{\"sets\":{}}", &mut state).await.unwrap());
+        assert!(!archive.store("earth", 160, b"{\"sets\":{}}", &mut state).await.unwrap());
+        assert!(archive.store("earth", 220, b"{\"sets\":{\"a\":{}}}", &mut state).await.unwrap());
+        assert_eq!(stored_times(&dir), [100, 220]);
+
+        // A fresh run recognises the newest snapshot, not an older one
+        let mut resumed = archive.resume("earth").await.unwrap();
+        assert_eq!(resumed.last_hash, state.last_hash);
+        assert!(!archive.store("earth", 280, b"{\"sets\":{\"a\":{}}}", &mut resumed).await.unwrap());
+        assert!(archive.store("earth", 340, b"{\"sets\":{}}", &mut resumed).await.unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn prunes_expired_snapshots_once_a_day() {
+        let (archive, dir) = local_archive("prune", Some(1)).await;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let mut state = ArchiveState::default();
+        archive.store("earth", now - 60, b"recent", &mut state).await.unwrap();
+        assert!(state.last_pruned.is_some());
+
+        // Pruned less than a day ago, so an expired snapshot stays for now
+        archive.store("earth", now - 3 * 86_400, b"old", &mut state).await.unwrap();
+        assert_eq!(stored_times(&dir), [now - 3 * 86_400, now - 60]);
+
+        state.last_pruned = state.last_pruned.map(|at| at - PRUNE_INTERVAL_SECS);
+        archive.store("earth", now, b"new", &mut state).await.unwrap();
+        assert_eq!(stored_times(&dir), [now - 60, now]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
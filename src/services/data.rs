@@ -1,344 +1,906 @@
 // Filename: services/data_fetcher.rs
 // Service to fetch data from a URL and extract information about a specific town
 
-use reqwest;
-use chrono;
-use regex::Regex;
-use serde_json::Value;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
 use tokio::time::{sleep, Duration};
 use aws_sdk_dynamodb::Client;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::option::Option;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::config::secret::{HistoryConfig, SourceConfig};
+use crate::models::dynmap::{AreaMarker, MarkerFile, MarkerSet};
+use crate::models::events::{TownEvent, TownEventKind};
+use crate::models::history::TownSnapshot;
+use crate::models::nations::Nation;
+use crate::models::players::Player;
 use crate::models::towns::Town;
+use crate::services::archive::{ArchiveState, SnapshotArchive};
+use crate::services::drift::ParseStats;
+use crate::services::ingestion::{backoff, IngestionMonitor};
+use crate::services::leaderboard::{RankSample, TownCache, BASELINE_AGE_SECS};
+use crate::services::settings::SettingsCache;
+use crate::services::watch::{self, Notifier, WatchCache};
+use crate::services::lineage;
+use crate::services::nation::aggregate_nations;
+use crate::services::player::index_players;
+use crate::services::geometry::{MultiPolygon, Polygon};
+use crate::services::parser::TownDescription;
+use crate::repositories::events::TownEventRepository;
+use crate::repositories::history::TownHistoryRepository;
+use crate::repositories::nations::NationRepository;
+use crate::repositories::players::PlayerRepository;
 use crate::repositories::towns::TownRepository;
 
-/// Fetches data from a specified URL in a loop, extracts information about a specific town,
-/// and prints the details. The loop runs indefinitely with a delay between each fetch.
+/// Upper bound on schema warnings printed per fetch, so one new upstream field
+/// does not flood the log with a line per town.
+const MAX_LOGGED_WARNINGS: usize = 20;
+
+/// Icon Dynmap-Towny gives the home block of a nation's capital.
+const CAPITAL_ICON: &str = "king";
+
+/// Longest delay between fetches while the map keeps failing.
+const MAX_BACKOFF: Duration = Duration::from_secs(900);
+
+/// Ingestion state carried from one cycle to the next for a single source.
+#[derive(Default)]
+pub struct SourceState {
+    history: HistoryConfig,
+    /// Validators from the last fully processed fetch, sent back as conditional request headers
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Newest stored row of every known town, fallen or alive, keyed by `town_name_lower`
+    towns: HashMap<String, Town>,
+    /// Last stored aggregate of every nation, keyed by `nation_name_lower`
+    nations: HashMap<String, Nation>,
+    /// Last stored index entry of every player, keyed by `player_name_lower`
+    players: HashMap<String, Player>,
+    previous_stats: Option<ParseStats>,
+    /// Dedup and pruning state of the snapshot archive
+    archive: ArchiveState,
+    /// Watched towns as of the last cycle watches were checked at, keyed by `town_name_lower`
+    watched: HashMap<String, Town>,
+    watched_at: Option<i64>,
+}
+
+impl SourceState {
+    pub fn new(history: HistoryConfig) -> Self {
+        Self {
+            history,
+            ..Default::default()
+        }
+    }
+}
+
+/// How many towns one ingestion cycle left alone, rewrote, added or lost.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CycleCounts {
+    pub unchanged: usize,
+    pub updated: usize,
+    pub created: usize,
+    /// Towns that reappeared under the name of a fallen town, starting a new epoch
+    pub refounded: usize,
+    pub renamed: usize,
+    pub merged: usize,
+    pub fallen: usize,
+}
+
+/// Fetches a source's marker file in a loop, extracts information about every town,
+/// and stores it in the source's namespace. The loop runs indefinitely with the
+/// source's poll interval between each fetch, backing off exponentially while
+/// fetches keep failing. Every cycle is reported to `monitor`, and every fetched
+/// marker file is stored in `archive` when one is configured.
 ///
 /// # Errors
 ///
 /// This function will return an error if there is an issue with the HTTP request or JSON parsing.
-pub async fn fetch_data(db_client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    let url = "https://map.ccnetmc.com/nationsmap/tiles/_markers_/marker_world.json";
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_data(
+    source: &SourceConfig,
+    db_client: &Client,
+    monitor: &IngestionMonitor,
+    archive: Option<&SnapshotArchive>,
+    history: &HistoryConfig,
+    cache: &TownCache,
+    watches: &WatchCache,
+    settings: &SettingsCache,
+    notifier: &Notifier,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let url = source.marker_url();
+    let http = reqwest::Client::new();
+    let mut state = SourceState::new(history.clone());
+
+    // Seed the known towns, nations and players from their tables so a restart
+    // neither rewrites every row nor forgets what fell, disbanded or left while
+    // it was down.
+    // Without them everything would look new, so a failure is left to the
+    // supervisor to retry.
+    state.towns = TownRepository::new(db_client, source).latest_towns().await?;
+    state.nations = NationRepository::new(db_client, source)
+        .all_nations()
+        .await?
+        .into_iter()
+        .map(|nation| (nation.nation_name_lower.clone(), nation))
+        .collect();
+    state.players = PlayerRepository::new(db_client, source)
+        .all_players()
+        .await?
+        .into_iter()
+        .map(|player| (player.player_name_lower.clone(), player))
+        .collect();
+    if let Some(archive) = archive {
+        match archive.resume(&source.name).await {
+            Ok(archive_state) => state.archive = archive_state,
+            Err(e) => println!("[{}] Could not read the newest archived snapshot: {}", source.name, e),
+        }
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    cache.update(&source.name, state.towns.values(), now);
+    if let Err(e) = seed_rank_baseline(source, db_client, cache, &state.towns, now).await {
+        println!("[{}] Could not load yesterday's towns for rank changes: {}", source.name, e);
+    }
 
     loop {
-        println!("Fetching data from URL: {}", url);
-        if let Err(e) = fetch_and_process_data(url, db_client).await {
-            println!("Error fetching or processing data: {}", e);
+        println!("[{}] Fetching data from URL: {}", source.name, url);
+        let started = Instant::now();
+        let mut delay = Duration::from_secs(source.poll_interval_secs);
+        let mut ingested_at = None;
+        match fetch_and_process_data(source, &http, &url, db_client, archive, &mut state).await {
+            Ok(Some((stats, counts))) => {
+                monitor.record_success(&source.name, started.elapsed());
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                cache.update(&source.name, state.towns.values(), now);
+                report_cycle(source, &mut state, stats, counts);
+                ingested_at = Some(now);
+            }
+            Ok(None) => monitor.record_success(&source.name, started.elapsed()),
+            Err(e) => {
+                println!("[{}] Error fetching or processing data: {}", source.name, e);
+                let failures = monitor.record_failure(&source.name, &e.to_string(), started.elapsed());
+                delay = backoff(delay, failures, MAX_BACKOFF);
+            }
         }
-        sleep(Duration::from_secs(60)).await;
+        // Checked outside the match, whose error is not `Send` across an await
+        if let Some(now) = ingested_at {
+            notify_watchers(source, db_client, watches, settings, notifier, &mut state, now).await;
+        }
+        sleep(delay).await;
     }
 }
 
-async fn fetch_and_process_data(url: &str, db_client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    let response = reqwest::get(url).await?;
-    if !response.status().is_success() {
-        println!("Failed to fetch JSON, status: {}", response.status());
-        return Ok(());
-    }
-
-    let json: Value = response.json().await?;
-    if let Some(towny) = json.pointer("/sets/towny.markerset") {
-        if let Some(areas) = towny.get("areas") {
-            if let Err(e) = process_areas(areas, db_client).await {
-                if e.to_string().contains("ValidationException") {
-                    println!("ValidationException occurred: {}", e);
-                    println!("Error details: {:?}", e);
-                } else {
-                    return Err(e);
-                }
+/// Checks every watch against the towns it covers and delivers what changed
+/// since the last check. Failures are logged and skipped so a missing table or
+/// an unreachable subscriber never holds up ingestion.
+async fn notify_watchers(
+    source: &SourceConfig,
+    db_client: &Client,
+    watches: &WatchCache,
+    settings: &SettingsCache,
+    notifier: &Notifier,
+    state: &mut SourceState,
+    now: i64,
+) {
+    let watches = match watches.all_watches(db_client, source).await {
+        Ok(watches) => watches,
+        Err(e) => {
+            println!("[{}] Could not load watches: {}", source.name, e);
+            return;
+        }
+    };
+    // Servers whose settings cannot be read fall back to the defaults
+    let guild_ids: HashSet<u64> = watches.iter().filter_map(|watch| watch.guild_id).collect();
+    let mut guilds = HashMap::new();
+    for guild_id in guild_ids {
+        match settings.get(db_client, guild_id).await {
+            Ok(guild) => {
+                guilds.insert(guild_id, guild);
             }
+            Err(e) => println!("[{}] Could not load the settings of server {}: {}", source.name, guild_id, e),
+        }
+    }
+    let current = watch::watched_towns(&watches, state.towns.values());
+    if let Some(previous_at) = state.watched_at {
+        for notification in watch::evaluate(&watches, &state.watched, previous_at, &current, now, &guilds) {
+            if let Err(e) = notifier.deliver(&notification).await {
+                println!("[{}] Could not deliver watch notification to {}: {}", source.name, notification.user_id, e);
+            }
+        }
+    }
+    state.watched = current;
+    state.watched_at = Some(now);
+}
+
+/// Fetches and ingests one marker file. Returns the cycle's parse statistics and
+/// write counts, or `None` when the file was unchanged or nothing was parsed.
+async fn fetch_and_process_data(
+    source: &SourceConfig,
+    http: &reqwest::Client,
+    url: &str,
+    db_client: &Client,
+    archive: Option<&SnapshotArchive>,
+    state: &mut SourceState,
+) -> Result<Option<(ParseStats, CycleCounts)>, Box<dyn std::error::Error>> {
+    let mut request = http.get(url);
+    if let Some(etag) = &state.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &state.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        println!("[{}] Marker file not modified since last fetch", source.name);
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch JSON, status: {}", response.status()).into());
+    }
+
+    let header = |name| {
+        response.headers().get(name)
+            .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+
+    let raw = response.bytes().await?;
+    if let Some(archive) = archive {
+        let fetched_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        if let Err(e) = archive.store(&source.name, fetched_at, &raw, &mut state.archive).await {
+            println!("[{}] Failed to archive marker file: {}", source.name, e);
+        }
+    }
+
+    let marker_file: MarkerFile = serde_json::from_slice(&raw)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let result = ingest_marker_file(source, &marker_file, now, db_client, state).await?;
+
+    // Only trust the validators once the whole file has been stored
+    state.etag = etag;
+    state.last_modified = last_modified;
+
+    Ok(Some(result))
+}
+
+/// Prints a cycle's parse statistics and write counts, raises drift alerts
+/// against the previous cycle, and keeps the statistics for the next one.
+pub fn report_cycle(source: &SourceConfig, state: &mut SourceState, stats: ParseStats, counts: CycleCounts) {
+    stats.report();
+    println!(
+        "[{}] Cycle complete: {} unchanged, {} updated, {} created, {} refounded, {} renamed, {} merged, {} fallen",
+        source.name, counts.unchanged, counts.updated, counts.created, counts.refounded, counts.renamed, counts.merged, counts.fallen
+    );
+    if let Some(previous) = &state.previous_stats {
+        for alert in stats.drift_alerts(previous) {
+            println!(
+                "[{}] PARSE DRIFT ALERT: `{}` hit rate fell from {:.1}% to {:.1}%; the description template may have changed",
+                source.name, alert.field, alert.previous_rate * 100.0, alert.current_rate * 100.0
+            );
         }
     }
+    state.previous_stats = Some(stats);
+}
+
+/// Runs one parsed marker file through the pipeline as if it was seen at
+/// `timestamp`, storing every town that changed since the previous file.
+pub async fn ingest_marker_file(
+    source: &SourceConfig,
+    marker_file: &MarkerFile,
+    timestamp: i64,
+    db_client: &Client,
+    state: &mut SourceState,
+) -> Result<(ParseStats, CycleCounts), Box<dyn std::error::Error>> {
+    let warnings = marker_file.warnings();
+    for warning in warnings.iter().take(MAX_LOGGED_WARNINGS) {
+        println!("Marker file schema warning: {}", warning);
+    }
+    if warnings.len() > MAX_LOGGED_WARNINGS {
+        println!("...and {} more schema warnings", warnings.len() - MAX_LOGGED_WARNINGS);
+    }
+
+    let Some(towny) = marker_file.sets.get(&source.marker_set) else {
+        return Err(format!("Marker set {} not found in marker file", source.marker_set).into());
+    };
+
+    let mut stats = ParseStats::default();
+    let towns = build_towns(source, towny, timestamp, &mut stats);
+    let counts = match store_changes(source, db_client, towns, timestamp, state).await {
+        Ok(counts) => counts,
+        Err(e) if e.to_string().contains("ValidationException") => {
+            println!("ValidationException occurred: {}", e);
+            println!("Error details: {:?}", e);
+            CycleCounts::default()
+        }
+        Err(e) => return Err(e),
+    };
+    store_nations(source, db_client, timestamp, state).await?;
+    store_players(source, db_client, timestamp, state).await?;
+
+    Ok((stats, counts))
+}
 
+/// Re-aggregates nations from the known towns and writes every nation that
+/// changed, plus a final row for every nation left without towns.
+async fn store_nations(
+    source: &SourceConfig,
+    db_client: &Client,
+    timestamp: i64,
+    state: &mut SourceState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let nations = aggregate_nations(state.towns.values(), timestamp);
+    let standing: HashSet<String> = nations.iter().map(|nation| nation.nation_name_lower.clone()).collect();
+    let mut changed: Vec<Nation> = nations
+        .into_iter()
+        .filter(|nation| {
+            state.nations.get(&nation.nation_name_lower)
+                .is_none_or(|known| known.is_disbanded() || known.content_hash != nation.content_hash)
+        })
+        .collect();
+    for nation in state.nations.values() {
+        if !nation.is_disbanded() && !standing.contains(&nation.nation_name_lower) {
+            println!("[{}] {} has disbanded", source.name, nation.nation_name);
+            let mut last = nation.clone();
+            last.disbanded_at = Some(timestamp);
+            last.last_updated = timestamp;
+            changed.push(last);
+        }
+    }
+
+    NationRepository::new(db_client, source).save_nations(&changed).await?;
+    println!("[{}] Stored {} changed nations", source.name, changed.len());
+    for nation in changed {
+        state.nations.insert(nation.nation_name_lower.clone(), nation);
+    }
+    Ok(())
+}
+
+/// Re-indexes players from the known towns and nations and writes every entry
+/// that changed. A player no longer found in any town gets an entry without a
+/// town, so lookups show them as unaffiliated rather than where they used to live.
+async fn store_players(
+    source: &SourceConfig,
+    db_client: &Client,
+    timestamp: i64,
+    state: &mut SourceState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let players = index_players(state.towns.values(), state.nations.values(), timestamp);
+    let indexed: HashSet<String> = players.iter().map(|player| player.player_name_lower.clone()).collect();
+    let mut changed: Vec<Player> = players
+        .into_iter()
+        .filter(|player| {
+            state.players.get(&player.player_name_lower)
+                .is_none_or(|known| known.content_hash != player.content_hash)
+        })
+        .collect();
+    for player in state.players.values() {
+        if !player.is_unaffiliated() && !indexed.contains(&player.player_name_lower) {
+            let mut last = player.clone();
+            last.town = None;
+            last.nation = None;
+            last.role = None;
+            last.trusted_in = Vec::new();
+            last.content_hash = last.compute_content_hash();
+            last.last_updated = timestamp;
+            changed.push(last);
+        }
+    }
+
+    PlayerRepository::new(db_client, source).save_players(&changed).await?;
+    println!("[{}] Stored {} changed players", source.name, changed.len());
+    for player in changed {
+        state.players.insert(player.player_name_lower.clone(), player);
+    }
     Ok(())
 }
 
-async fn process_areas(areas: &Value, db_client: &Client) -> Result<(), Box<dyn std::error::Error>> {
+/// Every claim polygon Dynmap publishes for one town, plus its home block.
+#[derive(Default)]
+struct TownClaims<'a> {
+    parts: Vec<(&'a str, &'a AreaMarker)>,
+    home: Option<(f64, f64)>,
+    home_icon: Option<&'a str>,
+    home_desc: Option<&'a str>,
+}
+
+/// Splits a Towny marker id such as `Town__0` or `Town__home` into the town
+/// name and the part suffix.
+fn split_marker_id(id: &str) -> (&str, &str) {
+    id.rsplit_once("__").unwrap_or((id, ""))
+}
+
+/// Turns every town in a Towny marker set into a `Town` stamped with `timestamp`.
+pub fn build_towns(source: &SourceConfig, set: &MarkerSet, timestamp: i64, stats: &mut ParseStats) -> Vec<Town> {
     println!("Processing areas...");
-    let mut astarte_found = false;
-    let mut towns: HashMap<String, (Option<&Value>, Option<&Value>)> = HashMap::new();
-
-    if let Some(areas_obj) = areas.as_object() {
-        for (name, area) in areas_obj {
-            let town_name = name.split("__").next().unwrap_or("");
-            let entry = towns.entry(town_name.to_string()).or_insert((None, None));
-            if name.ends_with("__home") {
-                entry.1 = Some(area);
-            } else {
-                entry.0 = Some(area);
+    let mut reference_found = false;
+    let mut claims_by_town: HashMap<&str, TownClaims> = HashMap::new();
+
+    for (id, area) in &set.areas {
+        let (town_name, part) = split_marker_id(id);
+        let claims = claims_by_town.entry(town_name).or_default();
+        if part == "home" {
+            claims.home_desc = area.desc.as_deref();
+        } else {
+            claims.parts.push((id, area));
+        }
+    }
+
+    // Home blocks are published as icon markers; they decide which part is the main claim
+    for (id, marker) in &set.markers {
+        let (town_name, part) = split_marker_id(id);
+        if part != "home" {
+            continue;
+        }
+        if let (Some(claims), Some(x), Some(z)) = (claims_by_town.get_mut(town_name), marker.x, marker.z) {
+            claims.home = Some((x, z));
+            claims.home_icon = marker.icon.as_deref();
+        }
+    }
+
+    let mut towns = Vec::with_capacity(claims_by_town.len());
+    for (town_name, mut claims) in claims_by_town {
+        if claims.parts.is_empty() {
+            continue;
+        }
+        claims.parts.sort_by_key(|(id, _)| *id);
+        let claim = MultiPolygon {
+            parts: claims.parts.iter().map(|(_, area)| Polygon::from_xz(&area.x, &area.z)).collect(),
+        };
+        let Some(main) = claim.main_part(claims.home) else {
+            continue;
+        };
+        let Some(desc) = claims.parts[main].1.desc.as_deref()
+            .or_else(|| claims.parts.iter().find_map(|(_, area)| area.desc.as_deref())) else {
+            continue;
+        };
+        let merged_desc = match claims.home_desc {
+            Some(home_desc) => format!("{}\n{}", desc, home_desc),
+            None => desc.to_owned(),
+        };
+
+        let is_reference = source.reference_town.as_deref() == Some(town_name);
+        reference_found |= is_reference;
+        let capital = claims.home_icon == Some(CAPITAL_ICON);
+        let mut town = process_town_data(town_name, &merged_desc, &claim, main, capital, stats, is_reference);
+        town.last_updated = timestamp;
+        towns.push(town);
+    }
+
+    if let (Some(reference_town), false) = (&source.reference_town, reference_found) {
+        println!("[{}] Reference town {} not found.", source.name, reference_town);
+    }
+
+    towns
+}
+
+/// Gives the leaderboards the towns as they stood a day before `now`, read
+/// from their history, so rank changes do not wait a day after a restart.
+async fn seed_rank_baseline(
+    source: &SourceConfig,
+    db_client: &Client,
+    cache: &TownCache,
+    towns: &HashMap<String, Town>,
+    now: i64,
+) -> Result<(), aws_sdk_dynamodb::Error> {
+    let at = now - BASELINE_AGE_SECS;
+    // Towns founded since were not standing then, and ones that fell before were not either
+    let founded: HashMap<&str, i64> = towns
+        .values()
+        .filter(|town| town.epoch <= at && town.fallen_at.is_none_or(|fallen_at| fallen_at > at))
+        .map(|town| (town.town_id.as_str(), town.founded))
+        .collect();
+    let town_ids = founded.keys().map(|&id| id.to_owned()).collect();
+    let points = TownHistoryRepository::new(db_client, source).points_at(town_ids, at).await?;
+    let samples = points
+        .iter()
+        .map(|point| RankSample::from_snapshot(point, founded.get(point.town_id.as_str()).copied().unwrap_or(0)))
+        .collect();
+    cache.seed_baseline(&source.name, samples, at);
+    Ok(())
+}
+
+/// Writes every changed town, every newly vanished town, every rename or
+/// merge event and a history point for every change to a tracked field, then
+/// remembers the written rows as the newest known state.
+async fn store_changes(
+    source: &SourceConfig,
+    db_client: &Client,
+    towns: Vec<Town>,
+    timestamp: i64,
+    state: &mut SourceState,
+) -> Result<CycleCounts, Box<dyn std::error::Error>> {
+    let mut changes = diff_towns(source, towns, timestamp, &state.towns);
+    let snapshots = history_points(&mut changes.towns, &state.towns, &state.history);
+    TownRepository::new(db_client, source).save_towns(&changes.towns).await?;
+    TownEventRepository::new(db_client, source).save_events(&changes.events).await?;
+    TownHistoryRepository::new(db_client, source).save_snapshots(&snapshots).await?;
+    for town in changes.towns {
+        state.towns.insert(town.town_name_lower.clone(), town);
+    }
+    Ok(changes.counts)
+}
+
+/// Builds a history point for every standing town in `changed` that is new
+/// or differs from its known row in a tracked field, and stamps the town with
+/// it. The first point of each UTC day per town, going by the stamp its row
+/// carries, gets the daily retention, every other point the regular one.
+fn history_points(changed: &mut [Town], known: &HashMap<String, Town>, config: &HistoryConfig) -> Vec<TownSnapshot> {
+    let expiry = |timestamp: i64, days: Option<u64>| days.map(|days| timestamp + days as i64 * 86_400);
+    changed
+        .iter_mut()
+        .filter(|town| !town.is_fallen())
+        .filter(|town| {
+            known.get(&town.town_name_lower)
+                .is_none_or(|old| old.is_fallen() || TownSnapshot::tracked_fields_differ(old, town))
+        })
+        .map(|town| {
+            let mut snapshot = TownSnapshot::from_town(town);
+            let day = town.last_updated.div_euclid(86_400);
+            let opens_day = town.history_at.is_none_or(|at| at.div_euclid(86_400) != day);
+            town.history_at = Some(town.last_updated);
+            snapshot.expires_at = match (opens_day, config.retention_days, config.daily_retention_days) {
+                // A daily point never expires before the regular points around it
+                (true, Some(regular), Some(daily)) => expiry(town.last_updated, Some(regular.max(daily))),
+                (true, _, _) => None,
+                (false, regular, _) => expiry(town.last_updated, regular),
+            };
+            snapshot
+        })
+        .collect()
+}
+
+/// Rows and events one cycle needs written.
+struct CycleChanges {
+    towns: Vec<Town>,
+    events: Vec<TownEvent>,
+    counts: CycleCounts,
+}
+
+/// Compares a cycle's towns against the known rows. Returns every town whose
+/// content hash differs from its known row, plus a final row for every known
+/// town missing from `towns`, stamped with `timestamp`.
+///
+/// A vanished town that matches a new town closely enough was renamed: the new
+/// town keeps its town ID and epoch. One whose residents moved into a
+/// neighbouring surviving town was merged into it. Any other vanished town has
+/// fallen, and a town seen again after falling starts a new epoch.
+fn diff_towns(
+    source: &SourceConfig,
+    towns: Vec<Town>,
+    timestamp: i64,
+    known: &HashMap<String, Town>,
+) -> CycleChanges {
+    let mut counts = CycleCounts::default();
+    let seen: HashSet<String> = towns.iter().map(|town| town.town_name_lower.clone()).collect();
+    let mut continuing = Vec::new();
+    let mut new = Vec::new();
+
+    for mut town in towns {
+        match known.get(&town.town_name_lower) {
+            Some(previous) if !previous.is_fallen() => {
+                town.town_id = previous.town_id.clone();
+                town.epoch = previous.epoch;
+                town.history_at = previous.history_at;
+                let changed = previous.content_hash != town.content_hash;
+                continuing.push((town, changed));
             }
+            _ => new.push(town),
         }
+    }
+
+    // An empty marker set is far more likely a broken map than every town vanishing at once
+    let mut vanished: Vec<&Town> = known
+        .values()
+        .filter(|town| !seen.is_empty() && !town.is_fallen() && !seen.contains(&town.town_name_lower))
+        .collect();
+    vanished.sort_by(|a, b| a.town_name_lower.cmp(&b.town_name_lower));
+
+    let renames = lineage::find_renames(&vanished, &new.iter().collect::<Vec<_>>());
+    let renamed_to: HashMap<usize, lineage::Link> = renames.iter().map(|link| (link.vanished, *link)).collect();
+    let renamed_from: HashMap<usize, usize> = renames.iter().map(|link| (link.successor, link.vanished)).collect();
+    let unmatched: Vec<usize> = (0..vanished.len()).filter(|i| !renamed_to.contains_key(i)).collect();
+    let merges = lineage::find_merges(
+        &unmatched.iter().map(|&i| vanished[i]).collect::<Vec<_>>(),
+        &continuing.iter().map(|(town, _)| town).collect::<Vec<_>>(),
+    );
+    let merged_into: HashMap<usize, (usize, f64)> = merges
+        .iter()
+        .map(|link| (unmatched[link.vanished], (link.successor, link.confidence)))
+        .collect();
+
+    let mut changed = Vec::new();
+    let mut events = Vec::new();
+    for (i, old) in vanished.iter().enumerate() {
+        let mut last = (*old).clone();
+        last.fallen_at = Some(timestamp);
+        last.last_updated = timestamp;
+        if let Some(link) = renamed_to.get(&i) {
+            let successor = &new[link.successor];
+            println!("[{}] {} was renamed to {}", source.name, old.town_name, successor.town_name);
+            last.renamed_to = Some(successor.town_name.clone());
+            events.push(TownEvent {
+                town_id: old.town_id.clone(),
+                kind: TownEventKind::Rename,
+                from_name: old.town_name.clone(),
+                to_name: successor.town_name.clone(),
+                target_id: old.town_id.clone(),
+                confidence: link.confidence,
+                timestamp,
+            });
+            counts.renamed += 1;
+        } else if let Some(&(j, confidence)) = merged_into.get(&i) {
+            let survivor = &continuing[j].0;
+            println!("[{}] {} was merged into {}", source.name, old.town_name, survivor.town_name);
+            last.merged_into = Some(survivor.town_name.clone());
+            events.push(TownEvent {
+                town_id: old.town_id.clone(),
+                kind: TownEventKind::Merge,
+                from_name: old.town_name.clone(),
+                to_name: survivor.town_name.clone(),
+                target_id: survivor.town_id.clone(),
+                confidence,
+                timestamp,
+            });
+            counts.merged += 1;
+        } else {
+            println!("[{}] {} has fallen", source.name, old.town_name);
+            counts.fallen += 1;
+        }
+        changed.push(last);
+    }
 
-        for (town_name, (main_area, home_area)) in towns {
-            if let Some(main_area) = main_area {
-                if let Some(desc) = main_area.get("desc").and_then(|d| d.as_str()) {
-                    let merged_desc = if let Some(home_area) = home_area {
-                        if let Some(home_desc) = home_area.get("desc").and_then(|d| d.as_str()) {
-                            format!("{}\n{}", desc, home_desc)
-                        } else {
-                            desc.to_string()
-                        }
-                    } else {
-                        desc.to_string()
-                    };
-
-                    if town_name == "Astarte" {
-                        astarte_found = true;
-                        process_town_data(&town_name, &merged_desc, db_client, true).await?;
-                    } else {
-                        process_town_data(&town_name, &merged_desc, db_client, false).await?;
-                    }
-                }
+    for (j, mut town) in new.into_iter().enumerate() {
+        if let Some(&i) = renamed_from.get(&j) {
+            town.town_id = vanished[i].town_id.clone();
+            town.epoch = vanished[i].epoch;
+            town.history_at = vanished[i].history_at;
+        } else {
+            town.epoch = timestamp;
+            town.town_id = Town::new_id(&town.town_name_lower, timestamp);
+            if known.contains_key(&town.town_name_lower) {
+                counts.refounded += 1;
+            } else {
+                counts.created += 1;
             }
         }
+        changed.push(town);
     }
 
-    if !astarte_found {
-        println!("Reference town Astarte not found.");
+    for (town, is_changed) in continuing {
+        if is_changed {
+            counts.updated += 1;
+            changed.push(town);
+        } else {
+            counts.unchanged += 1;
+        }
     }
 
-    Ok(())
+    CycleChanges { towns: changed, events, counts }
 }
 
-async fn process_town_data(name: &str, desc: &str, db_client: &Client, print_info: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let bank = extract_value(desc, "Bank");
-    let upkeep = extract_value(desc, "Upkeep");
-    let culture = extract_value(desc, "Culture");
-    let residents = extract_residents(desc);
-    let mayor = extract_value(desc, "Mayor");
-    let peaceful = extract_peaceful(desc);
-    let board = extract_value(desc, "Board");
-    let founded = extract_value(desc, "Founded");
-    let resources = extract_resources(desc);
-    let trusted_players = extract_trusted_players(desc);
-    let nation = extract_nation(desc);
-
-    let bank_value: f64 = parse_currency(&bank);
-    let upkeep_value: f64 = parse_currency(&upkeep);
+/// Parses one town's description and claim into a `Town`, with its content hash set.
+fn process_town_data(name: &str, desc: &str, claim: &MultiPolygon, main: usize, capital: bool, stats: &mut ParseStats, print_info: bool) -> Town {
+    let parsed = TownDescription::parse(desc);
+    stats.record(name, desc, &parsed);
+    let bank_value = parsed.bank.unwrap_or(0.0);
+    let upkeep_value = parsed.upkeep.unwrap_or(0.0);
     let will_go_negative = bank_value - upkeep_value < 0.0;
 
+    let area = claim.area();
+    let bounds = claim.bounding_box().unwrap_or_default();
+    let coords = claim.parts[main].representative_point().unwrap_or((0.0, 0.0));
+    let outposts: Vec<(f64, f64)> = claim.outposts(main)
+        .filter_map(Polygon::representative_point)
+        .collect();
+
     if print_info {
         println!(
-            "\n\nTown: {}\nNation: {}\nMayor: {}\nPeaceful: {}\nCulture: {}\nBoard: {}\nBank: ${:.2}\nUpkeep: ${:.2}\nFounded: {}\nResources: {:?}\nResidents: {:?}\nTrusted Players: {:?}\nWill go negative: {}\n",
-            name, nation, mayor, peaceful, culture, board, bank_value, upkeep_value, founded, resources, residents, trusted_players, will_go_negative
+            "\n\nTown: {}\nNation: {:?}\nMayor: {:?}\nPeaceful: {:?}\nCulture: {:?}\nBoard: {:?}\nBank: ${:.2}\nUpkeep: ${:.2}\nArea: {} blocks\nCoords: {:.0}, {:.0}\nOutposts: {}\nFounded: {:?}\nResources: {:?}\nResidents: {:?}\nTrusted Players: {:?}\nWill go negative: {}\n",
+            name, parsed.nation, parsed.mayor, parsed.peaceful, parsed.culture, parsed.board, bank_value, upkeep_value, area, coords.0, coords.1, outposts.len(), parsed.founded, parsed.resources, parsed.residents, parsed.trusted_players, will_go_negative
         );
     }
 
-    let town = Town {
+    let mut town = Town {
+        town_id: String::new(),
         town_name_lower: name.to_lowercase(),
-        town_name: name.to_string(),
-        nation: Some(nation),
-        mayor,
-        peaceful,
-        culture,
-        board,
+        town_name: name.to_owned(),
+        nation: parsed.nation,
+        capital,
+        mayor: parsed.mayor.unwrap_or_default(),
+        peaceful: parsed.peaceful.unwrap_or(false),
+        culture: parsed.culture.unwrap_or_default(),
+        board: parsed.board.unwrap_or_default(),
         bank: bank_value,
         upkeep: upkeep_value,
-        founded: parse_date(&founded),
-        resources,
-        residents,
-        trusted_players,
-        area: 0.0, // Assuming area is not available in the current context
-        coords: (0.0, 0.0), // Assuming coords are not available in the current context
-        last_updated: SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| Box::<dyn std::error::Error>::from(e))?.as_secs() as i64,
+        founded: parsed.founded.unwrap_or(0),
+        resources: parsed.resources.unwrap_or_default(),
+        residents: parsed.residents.unwrap_or_default(),
+        trusted_players: parsed.trusted_players.unwrap_or_default(),
+        area,
+        coords,
+        outposts,
+        bounds: (bounds.min_x, bounds.min_z, bounds.max_x, bounds.max_z),
+        content_hash: String::new(),
+        last_updated: 0,
+        epoch: 0,
+        fallen_at: None,
+        renamed_to: None,
+        merged_into: None,
+        history_at: None,
     };
-
-    save_town_data(db_client, town).await?;
-
-    Ok(())
+    town.content_hash = town.compute_content_hash();
+    town
 }
 
-pub async fn ensure_table_exists(db_client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    let table_name = "towns";
-    
-    // Check if table exists
-    let tables = db_client.list_tables().send().await?;
-    let opt_names: Option<&[String]> = Some(tables.table_names());
-    let table_names: &[String] = opt_names.unwrap_or(&[]);
-
-    if table_names.contains(&table_name.to_string()) {
-        println!("Table {} exists.", table_name);
-    } else {
-        println!("Table {} does not exist.", table_name);
-    }
-
-    Ok(())
-}
 
-pub async fn save_town_data(db_client: &Client, town: Town) -> Result<(), Box<dyn std::error::Error>> {
-    let repository = TownRepository::new(db_client);
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)?
-        .as_secs() as i64;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Update the town data with the current timestamp
-    let mut updated_town = town;
-    updated_town.last_updated = now;
-    repository.save_town(&updated_town).await?;
+    fn town(name: &str, bank: f64) -> Town {
+        let mut town = Town::test_town(name);
+        town.bank = bank;
+        town.content_hash = town.compute_content_hash();
+        town
+    }
 
-    Ok(())
-}
+    /// A town with its own mayor, residents and claim, unlike any other test town.
+    fn distinct_town(name: &str, offset: f64) -> Town {
+        let mut town = town(name, 10.0);
+        town.mayor = format!("{}Mayor", name);
+        town.residents = vec![town.mayor.clone(), format!("{}Resident", name)];
+        town.bounds = (offset, offset, offset + 16.0, offset + 16.0);
+        town.content_hash = town.compute_content_hash();
+        town
+    }
 
-/// Extracts a value associated with a given key from a description string.
-///
-/// # Arguments
-///
-/// * `desc` - A string slice that holds the description.
-/// * `key` - A string slice that holds the key to search for.
-///
-/// # Returns
-///
-/// A string containing the extracted value. If the key is not found, returns "0".
-fn extract_value(desc: &str, key: &str) -> String {
-    // This regex matches:
-    //   - a <span style="font-weight:bold"> tag with any characters (non-greedy) before the key
-    //   - the key (e.g., "Bank") followed by optional whitespace
-    //   - the closing </span> and a colon
-    //   - optional whitespace followed by the value (captured non-greedily)
-    //   - ending when a <br tag is encountered.
-    let pattern = format!(r#"<span style="font-weight:bold">.*?{}\s*</span>:\s*(.*?)<br"#, regex::escape(key));
-    let re = Regex::new(&pattern).unwrap();
-    if let Some(caps) = re.captures(desc) {
-        caps.get(1).map_or("0".to_string(), |m| m.as_str().trim().to_string())
-    } else {
-        "0".to_string()
+    /// Runs one cycle and applies its writes to `known`, as `store_changes` would.
+    fn cycle(known: &mut HashMap<String, Town>, towns: Vec<Town>, timestamp: i64) -> CycleChanges {
+        let towns = towns.into_iter().map(|mut t| { t.last_updated = timestamp; t }).collect();
+        let changes = diff_towns(&SourceConfig::test_source(), towns, timestamp, known);
+        for town in &changes.towns {
+            known.insert(town.town_name_lower.clone(), town.clone());
+        }
+        changes
     }
-}
 
-/// Extracts residents from the description string.
-/// 
-/// # Arguments
-/// 
-/// * `desc` - A string slice that holds the description.
-/// 
-/// # Returns
-/// 
-/// A vector of strings containing the residents' names.
-fn extract_residents(desc: &str) -> Vec<String> {
-    // This regex captures the text after the bold "Residents (n)" label until the next <br
-    let re = Regex::new(r#"<span style="font-weight:bold">.*?Residents\s*\(\d+\)\s*</span>:\s*(.*?)<br"#).unwrap();
-    if let Some(caps) = re.captures(desc) {
-        let residents_str = caps.get(1).map(|m| m.as_str().trim()).unwrap_or("");
-        residents_str.split(',')
-            .filter(|s| !s.is_empty())
-            .map(|s| s.trim().to_string())
-            .collect()
-    } else {
-        vec![]
+    fn with_bank(mut town: Town, bank: f64) -> Town {
+        town.bank = bank;
+        town.content_hash = town.compute_content_hash();
+        town
     }
-}
 
-/// Parses a currency string and converts it to a floating-point number.
-///
-/// # Arguments
-///
-/// * `value` - A string slice that holds the currency value.
-///
-/// # Returns
-///
-/// A floating-point number representing the parsed currency value. If parsing fails, returns 0.0.
-fn parse_currency(value: &str) -> f64 {
-    value.trim_start_matches('$').replace(",", "").parse().unwrap_or(0.0)
-}
+    #[test]
+    fn tracks_falls_and_refoundings() {
+        let astarte = distinct_town("Astarte", 0.0);
+        let lonely = distinct_town("Lonely", 500.0);
+        let mut known = HashMap::new();
+        let counts = cycle(&mut known, vec![astarte.clone(), lonely.clone()], 100).counts;
+        assert_eq!((counts.created, counts.fallen), (2, 0));
+        assert_eq!(known["astarte"].epoch, 100);
+        assert_eq!(known["astarte"].town_id, "astarte@100");
+
+        let counts = cycle(&mut known, vec![with_bank(astarte.clone(), 90.0), lonely.clone()], 200).counts;
+        assert_eq!((counts.updated, counts.unchanged), (1, 1));
+        assert_eq!(known["astarte"].epoch, 100);
+        assert_eq!(known["astarte"].town_id, "astarte@100");
+
+        // Lonely disappears: its final state is kept, stamped with the fall
+        let changes = cycle(&mut known, vec![with_bank(astarte.clone(), 90.0)], 300);
+        assert_eq!(changes.counts.fallen, 1);
+        assert!(changes.events.is_empty());
+        let fallen = &known["lonely"];
+        assert_eq!(fallen.fallen_at, Some(300));
+        assert_eq!(fallen.last_updated, 300);
+        assert_eq!(fallen.bank, 10.0);
+        assert_eq!(fallen.epoch, 100);
+
+        // Still gone: not reported again
+        let counts = cycle(&mut known, vec![with_bank(astarte.clone(), 90.0)], 400).counts;
+        assert_eq!((counts.fallen, counts.unchanged), (0, 1));
+
+        // Founded again under the same name, even with identical content
+        let counts = cycle(&mut known, vec![with_bank(astarte, 90.0), lonely], 500).counts;
+        assert_eq!(counts.refounded, 1);
+        let refounded = &known["lonely"];
+        assert_eq!(refounded.fallen_at, None);
+        assert_eq!(refounded.epoch, 500);
+        assert_eq!(refounded.town_id, "lonely@500");
+    }
 
-/// Extracts resources from the description string.
-/// 
-/// # Arguments
-/// 
-/// * `desc` - A string slice that holds the description.
-/// 
-/// # Returns
-/// 
-/// A vector of strings containing the resources.
-fn extract_resources(desc: &str) -> Vec<String> {
-    let re = Regex::new(r#"<span style=\"font-weight:bold\">.*?Resources\s*</span>:\s*(.*?)<br"#).unwrap();
-    if let Some(caps) = re.captures(desc) {
-        let resources_str = caps.get(1).map(|m| m.as_str().trim()).unwrap_or("");
-        return resources_str
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .collect();
-    }
-    vec![]
-}
+    #[test]
+    fn links_renamed_towns() {
+        let mut known = HashMap::new();
+        cycle(&mut known, vec![distinct_town("Astarte", 0.0), distinct_town("Lonely", 500.0)], 100);
+
+        let mut renamed = distinct_town("Astartia", 0.0);
+        renamed.mayor = "AstarteMayor".to_owned();
+        renamed.residents = vec!["AstarteMayor".to_owned(), "AstarteResident".to_owned()];
+        renamed.content_hash = renamed.compute_content_hash();
+        let changes = cycle(&mut known, vec![renamed, distinct_town("Lonely", 500.0)], 200);
+        assert_eq!((changes.counts.renamed, changes.counts.created, changes.counts.fallen), (1, 0, 0));
+        assert_eq!(known["astartia"].town_id, "astarte@100");
+        assert_eq!(known["astartia"].epoch, 100);
+        assert_eq!(known["astarte"].renamed_to.as_deref(), Some("Astartia"));
+        assert_eq!(known["astarte"].fallen_at, Some(200));
+
+        let event = &changes.events[0];
+        assert_eq!(event.kind, TownEventKind::Rename);
+        assert_eq!((event.from_name.as_str(), event.to_name.as_str()), ("Astarte", "Astartia"));
+        assert_eq!(event.target_id, "astarte@100");
+    }
 
-/// Extracts trusted players from the description string.
-/// 
-/// # Arguments
-/// 
-/// * `desc` - A string slice that holds the description.
-/// 
-/// # Returns
-/// 
-/// A vector of strings containing the trusted players' names.
-fn extract_trusted_players(desc: &str) -> Vec<String> {
-    let key_with_colon = "Trusted Players";
-    // Updated regex pattern to more robustly capture the trusted players
-    let re = Regex::new(r#"<span style=\"font-weight:bold\">.*?Trusted Players<\/span>: (.*?)(<br|<\/div>)"#).unwrap();
-    
-    if let Some(caps) = re.captures(desc) {
-        let players_str = caps.get(1).map(|m| m.as_str().trim()).unwrap_or("");
-        // Split by commas, trim whitespace, and collect as a vector of strings
-        return players_str.split(',')
-            .map(|s| s.trim().to_string())
-            .collect();
-    }
-    vec![] // Return an empty vector if no trusted players are found
-}
+    #[test]
+    fn links_merged_towns() {
+        let mut known = HashMap::new();
+        cycle(&mut known, vec![distinct_town("Astarte", 0.0), distinct_town("Annex", 16.0)], 100);
+
+        // Annex's residents move into its neighbour Astarte
+        let mut absorbed = distinct_town("Astarte", 0.0);
+        absorbed.residents.extend(["AnnexMayor".to_owned(), "AnnexResident".to_owned()]);
+        absorbed.bounds = (0.0, 0.0, 32.0, 32.0);
+        absorbed.content_hash = absorbed.compute_content_hash();
+        let changes = cycle(&mut known, vec![absorbed], 200);
+        assert_eq!((changes.counts.merged, changes.counts.updated, changes.counts.fallen), (1, 1, 0));
+        assert_eq!(known["annex"].merged_into.as_deref(), Some("Astarte"));
+
+        let event = &changes.events[0];
+        assert_eq!(event.kind, TownEventKind::Merge);
+        assert_eq!(event.town_id, "annex@100");
+        assert_eq!(event.target_id, "astarte@100");
+    }
 
-/// Parses a date string and converts it to a Unix timestamp.
-/// 
-/// # Arguments
-/// 
-/// * `date` - A string slice that holds the date.
-/// 
-/// # Returns
-/// 
-/// A Unix timestamp representing the parsed date. If parsing fails, returns 0.
-fn parse_date(date: &str) -> i64 {
-    // Assuming the date format is "Dec 1 2024"
-    let format = "%b %d %Y";
-    if let Ok(parsed_date) = chrono::NaiveDate::parse_from_str(date, format) {
-        parsed_date.and_hms_opt(0, 0, 0).map_or(0, |dt| dt.and_utc().timestamp())
-    } else {
-        0
+    #[test]
+    fn writes_history_points_for_tracked_changes() {
+        let config = HistoryConfig { retention_days: Some(7), daily_retention_days: Some(365) };
+        let mut known = HashMap::new();
+        let day = 1_733_011_200;
+        // Stores the rows and their points, as `store_changes` would
+        let store = |known: &mut HashMap<String, Town>, town: Town, timestamp: i64| {
+            let mut changes = cycle(&mut known.clone(), vec![town], timestamp);
+            let points = history_points(&mut changes.towns, known, &config);
+            for town in changes.towns {
+                known.insert(town.town_name_lower.clone(), town);
+            }
+            points
+        };
+
+        // A new town opens its day and gets the daily retention
+        let points = store(&mut known, distinct_town("Astarte", 0.0), day + 100);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].expires_at, Some(day + 100 + 365 * 86_400));
+        assert_eq!(known["astarte"].history_at, Some(day + 100));
+
+        // A later change the same day gets the regular retention, going by the
+        // stamp on the stored row rather than anything kept since startup
+        let points = store(&mut known, with_bank(distinct_town("Astarte", 0.0), 50.0), day + 200);
+        assert_eq!(points[0].bank, 50.0);
+        assert_eq!(points[0].expires_at, Some(day + 200 + 7 * 86_400));
+
+        // One resident leaving as another joins is a change, and the first one
+        // the next day opens that day
+        let mut swapped = with_bank(distinct_town("Astarte", 0.0), 50.0);
+        swapped.residents[1] = "Newcomer".to_owned();
+        swapped.content_hash = swapped.compute_content_hash();
+        let points = store(&mut known, swapped.clone(), day + 86_400 + 100);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].expires_at, Some(day + 86_400 + 100 + 365 * 86_400));
+
+        // Untracked fields alone do not make a point, and keep the stamp
+        let mut resources = swapped;
+        resources.resources = vec!["Iron".to_owned()];
+        resources.content_hash = resources.compute_content_hash();
+        assert!(store(&mut known, resources, day + 86_400 + 200).is_empty());
+        assert_eq!(known["astarte"].history_at, Some(day + 86_400 + 100));
     }
-}
 
-/// Extracts the nation name from the description string.
-/// 
-/// # Arguments
-/// 
-/// * `desc` - A string slice that holds the description.
-/// 
-/// # Returns
-/// 
-/// A string containing the nation name.
-fn extract_nation(desc: &str) -> String {
-    let re = Regex::new(r#"<span style="font-size:150%">Member of (.*?)</span>"#).unwrap();
-    if let Some(caps) = re.captures(desc) {
-        caps.get(1).map_or("".to_string(), |m| m.as_str().trim().to_string())
-    } else {
-        "".to_string()
+    #[test]
+    fn empty_cycle_does_not_fell_every_town() {
+        let mut known = HashMap::new();
+        cycle(&mut known, vec![distinct_town("Astarte", 0.0)], 100);
+        let counts = cycle(&mut known, Vec::new(), 200).counts;
+        assert_eq!(counts.fallen, 0);
+        assert!(!known["astarte"].is_fallen());
     }
 }
-
-/// Extracts the peaceful status from the description string.
-/// 
-/// # Arguments
-/// 
-/// * `desc` - A string slice that holds the description.
-/// 
-/// # Returns
-/// 
-/// A boolean indicating whether the town is peaceful.
-fn extract_peaceful(desc: &str) -> bool {
-    let re = Regex::new(r#"<span style="font-weight:bold">.*?Peaceful\?\s*</span>\s*(true|false)"#).unwrap();
-    if let Some(caps) = re.captures(desc) {
-        caps.get(1).map_or(false, |m| m.as_str().trim().to_lowercase() == "true")
-    } else {
-        false
-    }
-}
\ No newline at end of file
@@ -0,0 +1,294 @@
+// Filename: services/digest.rs
+// Daily digest of a nation's towns, posted to opted-in servers ahead of upkeep
+
+use crate::commands::pagination::{truncate_lines, FIELD_LIMIT};
+use crate::config::secret::SourceConfig;
+use crate::models::guilds::GuildSettings;
+use crate::models::towns::Town;
+use crate::repositories::towns::TownRepository;
+use crate::services::locale::format_money;
+use crate::services::settings::SettingsCache;
+use crate::services::upkeep::{fall_time, next_upkeep};
+use crate::services::watch::Notifier;
+use aws_sdk_dynamodb::Client;
+use chrono::{DateTime, Duration, Utc};
+use poise::serenity_prelude::{Colour, CreateEmbed};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use tokio::time::sleep;
+
+/// How often the scheduler checks whether a digest is due.
+const TICK: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Towns falling within this many days after the next upkeep are listed as low on funds.
+const LOW_FUNDS_DAYS: i64 = 3;
+
+/// What happened to a nation's towns since the previous digest, and which
+/// of them are about to fall.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Digest {
+    pub nation: String,
+    /// The upkeep collection the digest is posted ahead of
+    pub upkeep: i64,
+    /// Towns that fall at the next upkeep, with their bank and upkeep
+    pub falling: Vec<(String, f64, f64)>,
+    /// Towns that fall within `LOW_FUNDS_DAYS` after it, with their bank and fall time
+    pub low_funds: Vec<(String, f64, i64)>,
+    /// Towns founded in or joined to the nation
+    pub new_towns: Vec<String>,
+    /// Towns that fell, with the town they were renamed to or merged into, if any
+    pub fallen: Vec<(String, Option<String>)>,
+    /// Residents who joined each town
+    pub joined: Vec<(String, Vec<String>)>,
+    /// Residents who left each town
+    pub left: Vec<(String, Vec<String>)>,
+}
+
+/// Compares the towns of `nation` as stored at the previous digest with the
+/// `current` towns, as of `now`.
+pub fn build_digest(nation: &str, previous: &HashMap<String, Town>, current: &HashMap<String, Town>, now: DateTime<Utc>) -> Digest {
+    let in_nation = |town: &Town| town.nation.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(nation));
+    let standing_member = |town: &Town| !town.is_fallen() && in_nation(town);
+    let upkeep = next_upkeep(now);
+    let mut towns: Vec<&Town> = current.values().collect();
+    towns.sort_by(|a, b| a.town_name.cmp(&b.town_name));
+
+    let mut digest = Digest {
+        nation: nation.to_owned(),
+        upkeep: upkeep.timestamp(),
+        ..Default::default()
+    };
+    for town in towns {
+        let before = previous.get(&town.town_name_lower).filter(|before| standing_member(before));
+        if town.is_fallen() {
+            if before.is_some() {
+                digest.fallen.push((town.town_name.clone(), town.renamed_to.clone().or_else(|| town.merged_into.clone())));
+            }
+            continue;
+        }
+        if !in_nation(town) {
+            continue;
+        }
+
+        match fall_time(town.bank, town.upkeep, now) {
+            Some(falls) if falls == upkeep => digest.falling.push((town.town_name.clone(), town.bank, town.upkeep)),
+            Some(falls) if falls <= upkeep + Duration::days(LOW_FUNDS_DAYS) => {
+                digest.low_funds.push((town.town_name.clone(), town.bank, falls.timestamp()))
+            }
+            _ => {}
+        }
+
+        let Some(before) = before.filter(|before| before.town_id == town.town_id) else {
+            digest.new_towns.push(town.town_name.clone());
+            continue;
+        };
+        let was: HashSet<&String> = before.residents.iter().collect();
+        let is: HashSet<&String> = town.residents.iter().collect();
+        let mut joined: Vec<String> = is.difference(&was).map(|s| (*s).clone()).collect();
+        let mut left: Vec<String> = was.difference(&is).map(|s| (*s).clone()).collect();
+        joined.sort_unstable();
+        left.sort_unstable();
+        if !joined.is_empty() {
+            digest.joined.push((town.town_name.clone(), joined));
+        }
+        if !left.is_empty() {
+            digest.left.push((town.town_name.clone(), left));
+        }
+    }
+    digest
+}
+
+impl Digest {
+    pub fn embed(&self, colour: Colour, locale: Option<&str>) -> CreateEmbed {
+        let money = |amount: f64| format_money(amount, locale);
+        let falling = self.falling.iter().map(|(name, bank, upkeep)| {
+            format!("**{}**: {} in the bank, {} upkeep", name, money(*bank), money(*upkeep))
+        });
+        let low_funds = self.low_funds.iter().map(|(name, bank, falls)| {
+            format!("**{}**: {} in the bank, falls <t:{}:R>", name, money(*bank), falls)
+        });
+        let fallen = self.fallen.iter().map(|(name, successor)| match successor {
+            Some(successor) => format!("{} (now {})", name, successor),
+            None => name.clone(),
+        });
+        let residents = |changes: &[(String, Vec<String>)]| {
+            changes.iter().map(|(town, players)| format!("**{}**: {}", town, players.join(", "))).collect::<Vec<_>>()
+        };
+
+        let mut embed = CreateEmbed::default()
+            .title(format!("Upkeep digest for {}", self.nation))
+            .description(format!("Upkeep is collected <t:{}:R>", self.upkeep))
+            .colour(colour)
+            .field("Falling at upkeep", field_list(falling.collect(), "None"), false)
+            .field(format!("Low on funds (next {} days)", LOW_FUNDS_DAYS), field_list(low_funds.collect(), "None"), false);
+        for (title, lines) in [
+            ("New towns", self.new_towns.clone()),
+            ("Fallen towns", fallen.collect()),
+            ("Residents joined", residents(&self.joined)),
+            ("Residents left", residents(&self.left)),
+        ] {
+            if !lines.is_empty() {
+                embed = embed.field(title, field_list(lines, ""), false);
+            }
+        }
+        embed
+    }
+}
+
+/// One line per entry, cut short to fit in an embed field.
+fn field_list(lines: Vec<String>, empty: &str) -> String {
+    if lines.is_empty() {
+        return empty.to_owned();
+    }
+    truncate_lines(&lines, FIELD_LIMIT, "")
+}
+
+/// Whether the server wants a digest and has not had one for the upcoming upkeep yet.
+fn is_due(settings: &GuildSettings, now: DateTime<Utc>) -> bool {
+    let (Some(minutes), Some(_), Some(_)) = (settings.digest_minutes, settings.alerts_channel, &settings.default_nation) else {
+        return false;
+    };
+    let post_at = next_upkeep(now) - Duration::minutes(minutes as i64);
+    now >= post_at && settings.last_digest.is_none_or(|last| last < post_at.timestamp())
+}
+
+/// Starts the scheduler that posts each opted-in server's digest to its alerts
+/// channel, `digest_minutes` before every upkeep.
+pub fn spawn(sources: Vec<SourceConfig>, db_client: Client, settings: SettingsCache, notifier: Notifier) {
+    tokio::spawn(async move {
+        if let Err(e) = settings.load_all(&db_client).await {
+            println!("Could not load server settings for digests: {}", e);
+        }
+        loop {
+            let now = Utc::now();
+            let due: Vec<GuildSettings> = settings.all().into_iter().filter(|guild| is_due(guild, now)).collect();
+            for source in &sources {
+                let due_here: Vec<GuildSettings> = due.iter()
+                    .filter(|guild| guild.source_from(&sources).name == source.name)
+                    .cloned()
+                    .collect();
+                if !due_here.is_empty() {
+                    post_digests(source, &db_client, &settings, &notifier, due_here, now).await;
+                }
+            }
+            sleep(TICK).await;
+        }
+    });
+}
+
+async fn post_digests(
+    source: &SourceConfig,
+    db_client: &Client,
+    settings: &SettingsCache,
+    notifier: &Notifier,
+    due: Vec<GuildSettings>,
+    now: DateTime<Utc>,
+) {
+    let repository = TownRepository::new(db_client, source);
+    let current = match repository.latest_towns().await {
+        Ok(towns) => towns,
+        Err(e) => {
+            println!("Could not load towns for digests: {}", e);
+            return;
+        }
+    };
+
+    // Servers that post at the same time share their previous digest too, so
+    // each distinct one is only looked up once
+    let mut previous_at: HashMap<i64, HashMap<String, Town>> = HashMap::new();
+    for guild in due {
+        let (Some(channel_id), Some(nation)) = (guild.alerts_channel, &guild.default_nation) else {
+            continue;
+        };
+        let since = guild.last_digest.unwrap_or_else(|| (now - Duration::days(1)).timestamp());
+        let previous = match previous_at.entry(since) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match repository.towns_at(since).await {
+                Ok(towns) => entry.insert(towns),
+                Err(e) => {
+                    println!("Could not load towns as of the last digest of guild {}: {}", guild.guild_id, e);
+                    continue;
+                }
+            },
+        };
+
+        let digest = build_digest(nation, previous, &current, now);
+        if let Err(e) = notifier.post(channel_id, digest.embed(guild.colour(), guild.locale.as_deref())).await {
+            println!("Could not post the digest of guild {}: {}", guild.guild_id, e);
+        }
+
+        // Marked as sent even when posting failed, so a missing permission is not retried every minute
+        let guild_id = guild.guild_id;
+        let mut latest = settings.get(db_client, guild_id).await.unwrap_or(guild);
+        latest.last_digest = Some(now.timestamp());
+        if let Err(e) = settings.save(db_client, latest).await {
+            println!("Could not record the digest of guild {}: {}", guild_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::upkeep::TEST_NOON;
+
+    fn member(name: &str, bank: f64) -> Town {
+        let mut town = Town::test_town(name);
+        town.nation = Some("Rome".to_owned());
+        town.bank = bank;
+        town
+    }
+
+    fn towns(towns: &[Town]) -> HashMap<String, Town> {
+        towns.iter().map(|town| (town.town_name_lower.clone(), town.clone())).collect()
+    }
+
+    #[test]
+    fn digests_a_nations_changes() {
+        let now = DateTime::from_timestamp(TEST_NOON, 0).unwrap();
+        let upkeep = now.timestamp() + 7 * 3600;
+        let growing = member("Astarte", 1000.0);
+        let mut grown = growing.clone();
+        grown.residents.push("Notch".to_owned());
+        let doomed = member("Broke", 0.0);
+        let low = member("Thrifty", 15.0);
+        let ruin = member("Ruin", 50.0);
+        let mut ruined = ruin.clone();
+        ruined.fallen_at = Some(now.timestamp() - 60);
+        let founded = member("Upstart", 500.0);
+        let mut foreign = Town::test_town("Gaul");
+        foreign.bank = 0.0;
+
+        let previous = towns(&[growing, doomed.clone(), low.clone(), ruin, foreign.clone()]);
+        let current = towns(&[grown, doomed, low, ruined, founded, foreign]);
+        let digest = build_digest("rome", &previous, &current, now);
+        assert_eq!(digest, Digest {
+            nation: "rome".to_owned(),
+            upkeep,
+            falling: vec![("Broke".to_owned(), 0.0, 10.0)],
+            low_funds: vec![("Thrifty".to_owned(), 15.0, upkeep + 2 * 86_400)],
+            new_towns: vec!["Upstart".to_owned()],
+            fallen: vec![("Ruin".to_owned(), None)],
+            joined: vec![("Astarte".to_owned(), vec!["Notch".to_owned()])],
+            left: Vec::new(),
+        });
+    }
+
+    #[test]
+    fn posts_once_per_upkeep() {
+        let mut settings = GuildSettings::new(1);
+        settings.digest_minutes = Some(60);
+        settings.alerts_channel = Some(2);
+        settings.default_nation = Some("Rome".to_owned());
+        let at = |hour: u32| DateTime::parse_from_rfc3339(&format!("2024-12-01T{:02}:30:00Z", hour)).unwrap().to_utc();
+
+        assert!(!is_due(&settings, at(17)));
+        assert!(is_due(&settings, at(18)));
+        settings.last_digest = Some(at(18).timestamp());
+        assert!(!is_due(&settings, at(18)));
+        // The next day's digest is due again
+        assert!(is_due(&settings, at(18) + Duration::days(1)));
+        settings.alerts_channel = None;
+        assert!(!is_due(&settings, at(18) + Duration::days(1)));
+    }
+}
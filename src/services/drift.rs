@@ -0,0 +1,196 @@
+// Filename: services/drift.rs
+// Per-cycle parse statistics, used to notice when CCNet changes its description template
+
+use crate::services::parser::TownDescription;
+use std::collections::BTreeMap;
+
+/// Fields every town description is expected to carry. A town missing any of
+/// these is reported as a parse failure; the rest are legitimately optional.
+const REQUIRED_FIELDS: &[&str] = &["mayor", "peaceful", "bank", "upkeep", "founded", "residents"];
+
+/// Number of offending descriptions kept per cycle for the report.
+const MAX_SAMPLES: usize = 3;
+
+/// Descriptions are cut to this many characters in the report.
+const SAMPLE_LENGTH: usize = 600;
+
+/// An alert fires when a field's hit rate falls by at least this much between cycles.
+const ALERT_DROP: f64 = 0.2;
+
+/// Slack for rounding when a rate drops by exactly `ALERT_DROP`, e.g. 1.0 to 0.8.
+const RATE_TOLERANCE: f64 = 1e-9;
+
+/// Cycles with fewer towns than this are too small to compare hit rates reliably.
+const MIN_TOWNS_FOR_ALERT: usize = 10;
+
+/// A town whose description was missing required fields.
+#[derive(Debug, Clone)]
+pub struct ParseFailure {
+    pub town: String,
+    pub missing: Vec<&'static str>,
+    pub sample: Option<String>,
+}
+
+/// A field whose hit rate dropped sharply compared to the previous cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftAlert {
+    pub field: &'static str,
+    pub previous_rate: f64,
+    pub current_rate: f64,
+}
+
+/// Parse statistics for one ingestion cycle.
+#[derive(Debug, Clone, Default)]
+pub struct ParseStats {
+    pub towns: usize,
+    pub hits: BTreeMap<&'static str, usize>,
+    pub failures: Vec<ParseFailure>,
+}
+
+impl ParseStats {
+    pub fn record(&mut self, town: &str, desc: &str, parsed: &TownDescription) {
+        self.towns += 1;
+        let mut missing = Vec::new();
+        for (field, present) in parsed.field_presence() {
+            let hits = self.hits.entry(field).or_insert(0);
+            if present {
+                *hits += 1;
+            } else if REQUIRED_FIELDS.contains(&field) {
+                missing.push(field);
+            }
+        }
+        if !missing.is_empty() {
+            let sample = (self.failures.len() < MAX_SAMPLES)
+                .then(|| desc.chars().take(SAMPLE_LENGTH).collect());
+            self.failures.push(ParseFailure {
+                town: town.to_owned(),
+                missing,
+                sample,
+            });
+        }
+    }
+
+    /// Share of towns in which `field` was found, from 0.0 to 1.0.
+    pub fn hit_rate(&self, field: &str) -> f64 {
+        if self.towns == 0 {
+            return 0.0;
+        }
+        self.hits.get(field).copied().unwrap_or(0) as f64 / self.towns as f64
+    }
+
+    /// Compares this cycle against the previous one and returns every field
+    /// whose hit rate dropped by at least `ALERT_DROP`.
+    pub fn drift_alerts(&self, previous: &ParseStats) -> Vec<DriftAlert> {
+        if self.towns < MIN_TOWNS_FOR_ALERT || previous.towns < MIN_TOWNS_FOR_ALERT {
+            return Vec::new();
+        }
+        previous
+            .hits
+            .keys()
+            .filter_map(|&field| {
+                let previous_rate = previous.hit_rate(field);
+                let current_rate = self.hit_rate(field);
+                (previous_rate - current_rate + RATE_TOLERANCE >= ALERT_DROP).then_some(DriftAlert {
+                    field,
+                    previous_rate,
+                    current_rate,
+                })
+            })
+            .collect()
+    }
+
+    /// Prints hit rates, failure counts and the sampled offending descriptions.
+    pub fn report(&self) {
+        let rates: Vec<String> = self
+            .hits
+            .keys()
+            .map(|field| format!("{} {:.1}%", field, self.hit_rate(field) * 100.0))
+            .collect();
+        println!("Parsed {} towns; field hit rates: {}", self.towns, rates.join(", "));
+
+        if self.failures.is_empty() {
+            return;
+        }
+        println!("{} towns have missing or unparseable fields", self.failures.len());
+        for failure in &self.failures {
+            if let Some(sample) = &failure.sample {
+                println!("  {} is missing {}: {}", failure.town, failure.missing.join(", "), sample);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete() -> TownDescription {
+        TownDescription {
+            mayor: Some("Steve".to_owned()),
+            peaceful: Some(false),
+            bank: Some(100.0),
+            upkeep: Some(10.0),
+            founded: Some(0),
+            residents: Some(vec!["Steve".to_owned()]),
+            ..Default::default()
+        }
+    }
+
+    fn without_bank() -> TownDescription {
+        TownDescription { bank: None, ..complete() }
+    }
+
+    /// A cycle of `towns` towns, the first `missing_bank` of which lack a bank.
+    fn cycle(towns: usize, missing_bank: usize) -> ParseStats {
+        let mut stats = ParseStats::default();
+        for i in 0..towns {
+            let parsed = if i < missing_bank { without_bank() } else { complete() };
+            stats.record(&format!("Town{}", i), "desc", &parsed);
+        }
+        stats
+    }
+
+    #[test]
+    fn counts_hit_rates() {
+        let stats = cycle(4, 1);
+        assert_eq!(stats.towns, 4);
+        assert_eq!(stats.hit_rate("bank"), 0.75);
+        assert_eq!(stats.hit_rate("mayor"), 1.0);
+        assert_eq!(stats.hit_rate("nation"), 0.0);
+        assert_eq!(stats.hit_rate("unknown"), 0.0);
+        assert_eq!(ParseStats::default().hit_rate("bank"), 0.0);
+        // Only required fields make a town a failure
+        assert_eq!(stats.failures.len(), 1);
+        assert_eq!(stats.failures[0].missing, ["bank"]);
+    }
+
+    #[test]
+    fn keeps_a_few_shortened_samples() {
+        let mut stats = ParseStats::default();
+        let desc = "x".repeat(SAMPLE_LENGTH * 2);
+        for i in 0..MAX_SAMPLES + 2 {
+            stats.record(&format!("Town{}", i), &desc, &without_bank());
+        }
+        assert_eq!(stats.failures.len(), MAX_SAMPLES + 2);
+        let samples: Vec<_> = stats.failures.iter().filter_map(|f| f.sample.as_ref()).collect();
+        assert_eq!(samples.len(), MAX_SAMPLES);
+        assert!(samples.iter().all(|sample| sample.len() == SAMPLE_LENGTH));
+    }
+
+    #[test]
+    fn alerts_on_drops_at_the_threshold() {
+        let previous = cycle(10, 0);
+        // 2 of 10 towns lose their bank: a drop of exactly ALERT_DROP
+        let alerts = cycle(10, 2).drift_alerts(&previous);
+        assert_eq!(alerts, [DriftAlert { field: "bank", previous_rate: 1.0, current_rate: 0.8 }]);
+        assert!(cycle(10, 1).drift_alerts(&previous).is_empty());
+    }
+
+    #[test]
+    fn skips_small_cycles_and_the_first_cycle() {
+        let small = MIN_TOWNS_FOR_ALERT - 1;
+        assert!(cycle(small, small).drift_alerts(&cycle(small, 0)).is_empty());
+        assert!(cycle(10, 10).drift_alerts(&cycle(small, 0)).is_empty());
+        assert!(cycle(10, 10).drift_alerts(&ParseStats::default()).is_empty());
+    }
+}
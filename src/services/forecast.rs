@@ -0,0 +1,117 @@
+// Filename: services/forecast.rs
+// Bank balance trend and fall forecast from a town's recorded history
+
+use crate::services::upkeep::fall_time;
+use chrono::{DateTime, Utc};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Estimated daily net income of a town and when it falls if the trend holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceForecast {
+    /// Net balance change per day, deposits minus upkeep
+    pub daily_net: f64,
+    /// Number of whole days of history the trend is fitted over
+    pub days_observed: i64,
+    /// Fall time if income is one standard error better than the trend, `None` if the town never falls
+    pub optimistic_fall: Option<DateTime<Utc>>,
+    /// Fall time if income is one standard error worse than the trend, `None` if the town never falls
+    pub pessimistic_fall: Option<DateTime<Utc>>,
+}
+
+/// Fits a trend to a town's balance over the last `window_days` days.
+///
+/// `points` are `(unix time, bank)` change points, oldest first, ending with
+/// the current balance. The balance holds between points, so it is sampled once
+/// a day going back from `now` and a least-squares line is fitted through the
+/// samples. Returns `None` with less than a day of history.
+pub fn forecast(points: &[(i64, f64)], now: DateTime<Utc>, window_days: i64) -> Option<BalanceForecast> {
+    let (first_time, _) = *points.first()?;
+    let samples: Vec<(f64, f64)> = (0..=window_days)
+        .map(|days_ago| now.timestamp() - days_ago * SECONDS_PER_DAY as i64)
+        .take_while(|&time| time >= first_time)
+        .filter_map(|time| {
+            let (_, bank) = points.iter().rev().find(|(t, _)| *t <= time)?;
+            Some(((time - now.timestamp()) as f64 / SECONDS_PER_DAY, *bank))
+        })
+        .collect();
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = samples.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = samples.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let slope = sxy / sxx;
+
+    // Standard error of the slope; a two-point fit has no residuals to measure it by
+    let standard_error = if samples.len() > 2 {
+        let residuals: f64 = samples
+            .iter()
+            .map(|(x, y)| (y - (mean_y + slope * (x - mean_x))).powi(2))
+            .sum();
+        (residuals / (n - 2.0)).sqrt() / sxx.sqrt()
+    } else {
+        0.0
+    };
+
+    let bank = points.last().map_or(0.0, |(_, bank)| *bank);
+    let fall_at = |daily_net: f64| fall_time(bank, -daily_net, now);
+    Some(BalanceForecast {
+        daily_net: slope,
+        days_observed: samples.len() as i64 - 1,
+        optimistic_fall: fall_at(slope + standard_error),
+        pessimistic_fall: fall_at(slope - standard_error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::upkeep::TEST_NOON as NOW;
+
+    const DAY: i64 = 86_400;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(NOW, 0).unwrap()
+    }
+
+    #[test]
+    fn steady_decline_has_equal_bounds() {
+        let points: Vec<(i64, f64)> = (0..=7).rev().map(|d| (NOW - d * DAY, 1000.0 - (7 - d) as f64 * 100.0)).collect();
+        let forecast = forecast(&points, now(), 7).unwrap();
+        assert!((forecast.daily_net + 100.0).abs() < 1e-9);
+        assert_eq!(forecast.days_observed, 7);
+        // $300 left at $100 a day: three more upkeeps are paid
+        let expected = DateTime::from_timestamp(NOW + 7 * 3600 + 3 * DAY, 0);
+        assert_eq!(forecast.pessimistic_fall, expected);
+        assert_eq!(forecast.optimistic_fall, expected);
+    }
+
+    #[test]
+    fn sparse_change_points_hold_between_samples() {
+        // One deposit five days ago, otherwise flat: the trend is positive
+        let points = [(NOW - 10 * DAY, 100.0), (NOW - 5 * DAY, 500.0), (NOW, 500.0)];
+        let forecast = forecast(&points, now(), 7).unwrap();
+        assert!(forecast.daily_net > 0.0);
+        assert_eq!(forecast.optimistic_fall, None);
+    }
+
+    #[test]
+    fn noisy_history_brackets_the_fall() {
+        let banks = [1000.0, 980.0, 870.0, 860.0, 700.0, 720.0, 600.0, 560.0];
+        let points: Vec<(i64, f64)> = banks.iter().enumerate().map(|(i, b)| (NOW - (7 - i as i64) * DAY, *b)).collect();
+        let forecast = forecast(&points, now(), 7).unwrap();
+        assert!(forecast.daily_net < 0.0);
+        let (pessimistic, optimistic) = (forecast.pessimistic_fall.unwrap(), forecast.optimistic_fall.unwrap());
+        assert!(pessimistic < optimistic);
+    }
+
+    #[test]
+    fn needs_a_day_of_history() {
+        assert_eq!(forecast(&[(NOW - 3600, 100.0), (NOW, 90.0)], now(), 7), None);
+        assert_eq!(forecast(&[], now(), 7), None);
+    }
+}
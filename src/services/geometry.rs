@@ -0,0 +1,331 @@
+// Filename: services/geometry.rs
+// Polygon measurements for Dynmap claim areas
+
+/// Axis-aligned bounding box in block coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BoundingBox {
+    pub min_x: f64,
+    pub min_z: f64,
+    pub max_x: f64,
+    pub max_z: f64,
+}
+
+impl BoundingBox {
+    /// Returns true when the boxes overlap or share an edge.
+    pub fn touches(&self, other: &BoundingBox) -> bool {
+        self.min_x <= other.max_x
+            && other.min_x <= self.max_x
+            && self.min_z <= other.max_z
+            && other.min_z <= self.max_z
+    }
+
+    pub fn area(&self) -> f64 {
+        (self.max_x - self.min_x).max(0.0) * (self.max_z - self.min_z).max(0.0)
+    }
+
+    /// Returns the box both boxes cover, if they overlap.
+    pub fn intersection(&self, other: &BoundingBox) -> Option<BoundingBox> {
+        self.touches(other).then(|| BoundingBox {
+            min_x: self.min_x.max(other.min_x),
+            min_z: self.min_z.max(other.min_z),
+            max_x: self.max_x.min(other.max_x),
+            max_z: self.max_z.min(other.max_z),
+        })
+    }
+
+    /// Intersection over union of the two boxes, from 0.0 (disjoint) to 1.0 (identical).
+    pub fn overlap(&self, other: &BoundingBox) -> f64 {
+        let shared = self.intersection(other).map_or(0.0, |b| b.area());
+        let total = self.area() + other.area() - shared;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        shared / total
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x.min(other.min_x),
+            min_z: self.min_z.min(other.min_z),
+            max_x: self.max_x.max(other.max_x),
+            max_z: self.max_z.max(other.max_z),
+        }
+    }
+}
+
+/// A simple polygon given by its `x`/`z` vertices, as Dynmap publishes them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Polygon {
+    pub vertices: Vec<(f64, f64)>,
+}
+
+impl Polygon {
+    /// Builds a polygon from Dynmap's parallel `x` and `z` arrays.
+    /// Extra vertices in the longer array are ignored.
+    pub fn from_xz(x: &[f64], z: &[f64]) -> Self {
+        Self {
+            vertices: x.iter().copied().zip(z.iter().copied()).collect(),
+        }
+    }
+
+    /// Returns false when the polygon has too few vertices to enclose any area.
+    pub fn is_valid(&self) -> bool {
+        self.vertices.len() >= 3
+    }
+
+    /// Signed area via the shoelace formula; positive for counter-clockwise winding.
+    fn signed_area(&self) -> f64 {
+        let n = self.vertices.len();
+        let mut sum = 0.0;
+        for i in 0..n {
+            let (x1, z1) = self.vertices[i];
+            let (x2, z2) = self.vertices[(i + 1) % n];
+            sum += x1 * z2 - x2 * z1;
+        }
+        sum / 2.0
+    }
+
+    /// Enclosed area in square blocks.
+    pub fn area(&self) -> f64 {
+        if !self.is_valid() {
+            return 0.0;
+        }
+        self.signed_area().abs()
+    }
+
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let (&(x, z), rest) = self.vertices.split_first()?;
+        let mut bbox = BoundingBox { min_x: x, min_z: z, max_x: x, max_z: z };
+        for &(x, z) in rest {
+            bbox.min_x = bbox.min_x.min(x);
+            bbox.min_z = bbox.min_z.min(z);
+            bbox.max_x = bbox.max_x.max(x);
+            bbox.max_z = bbox.max_z.max(z);
+        }
+        Some(bbox)
+    }
+
+    /// Even-odd point-in-polygon test.
+    pub fn contains(&self, point: (f64, f64)) -> bool {
+        let (px, pz) = point;
+        let n = self.vertices.len();
+        let mut inside = false;
+        let mut j = n.wrapping_sub(1);
+        for i in 0..n {
+            let (xi, zi) = self.vertices[i];
+            let (xj, zj) = self.vertices[j];
+            if (zi > pz) != (zj > pz) && px < (xj - xi) * (pz - zi) / (zj - zi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+
+    /// The polygon's edges as pairs of consecutive vertices, closing back to the first.
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+
+    /// Returns true when the polygons share a stretch of boundary, as claims
+    /// Dynmap split out of one connected area do. Touching at a corner does not count.
+    pub fn shares_edge(&self, other: &Polygon) -> bool {
+        self.edges().any(|edge| other.edges().any(|other_edge| segments_overlap(edge, other_edge)))
+    }
+
+    /// Returns a point guaranteed to lie inside the polygon, even a concave one.
+    ///
+    /// Scans a horizontal line through the vertical middle of the polygon,
+    /// halfway between two vertex rows so it never passes through a vertex,
+    /// and returns the middle of the widest span it crosses inside the polygon.
+    pub fn representative_point(&self) -> Option<(f64, f64)> {
+        if self.area() == 0.0 {
+            return None;
+        }
+        let bbox = self.bounding_box()?;
+        let middle = (bbox.min_z + bbox.max_z) / 2.0;
+        let below = self.vertices.iter().map(|&(_, z)| z).filter(|&z| z <= middle).fold(f64::MIN, f64::max);
+        let above = self.vertices.iter().map(|&(_, z)| z).filter(|&z| z > middle).fold(f64::MAX, f64::min);
+        let scan_z = (below + above) / 2.0;
+
+        let n = self.vertices.len();
+        let mut crossings = Vec::new();
+        for i in 0..n {
+            let (x1, z1) = self.vertices[i];
+            let (x2, z2) = self.vertices[(i + 1) % n];
+            if (z1 > scan_z) != (z2 > scan_z) {
+                crossings.push(x1 + (scan_z - z1) * (x2 - x1) / (z2 - z1));
+            }
+        }
+        crossings.sort_by(|a, b| a.total_cmp(b));
+        crossings
+            .chunks_exact(2)
+            .max_by(|a, b| (a[1] - a[0]).total_cmp(&(b[1] - b[0])))
+            .map(|span| ((span[0] + span[1]) / 2.0, scan_z))
+    }
+}
+
+/// A claim made of one or more disjoint polygons, e.g. a town and its outposts.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MultiPolygon {
+    pub parts: Vec<Polygon>,
+}
+
+impl MultiPolygon {
+    /// Total enclosed area of every part in square blocks.
+    pub fn area(&self) -> f64 {
+        self.parts.iter().map(Polygon::area).sum()
+    }
+
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.parts
+            .iter()
+            .filter_map(Polygon::bounding_box)
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Picks the index of the main claim: the part containing `home` if given,
+    /// otherwise the largest part.
+    pub fn main_part(&self, home: Option<(f64, f64)>) -> Option<usize> {
+        if let Some(home) = home {
+            if let Some(index) = self.parts.iter().position(|part| part.contains(home)) {
+                return Some(index);
+            }
+        }
+        self.parts
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.area().total_cmp(&b.area()))
+            .map(|(index, _)| index)
+    }
+
+    /// Parts that are detached from the main claim, i.e. outposts.
+    ///
+    /// Parts connected to the main part through shared edges, directly or via
+    /// other parts, are pieces of the main claim that Dynmap split up, not outposts.
+    pub fn outposts(&self, main: usize) -> impl Iterator<Item = &Polygon> {
+        let mut connected = vec![false; self.parts.len()];
+        let mut pending = Vec::new();
+        if main < self.parts.len() {
+            connected[main] = true;
+            pending.push(main);
+        }
+        while let Some(index) = pending.pop() {
+            for (other, part) in self.parts.iter().enumerate() {
+                if !connected[other] && part.shares_edge(&self.parts[index]) {
+                    connected[other] = true;
+                    pending.push(other);
+                }
+            }
+        }
+        self.parts.iter().zip(connected).filter_map(|(part, connected)| (!connected).then_some(part))
+    }
+}
+
+/// Returns true when two segments lie on the same line and overlap for more than a point.
+fn segments_overlap(a: ((f64, f64), (f64, f64)), b: ((f64, f64), (f64, f64))) -> bool {
+    const EPSILON: f64 = 1e-9;
+    let ((ax, az), (bx, bz)) = a;
+    let (dx, dz) = (bx - ax, bz - az);
+    let length_squared = dx * dx + dz * dz;
+    if length_squared == 0.0 {
+        return false;
+    }
+    let cross = |(x, z): (f64, f64)| dx * (z - az) - dz * (x - ax);
+    if cross(b.0).abs() > EPSILON * length_squared || cross(b.1).abs() > EPSILON * length_squared {
+        return false;
+    }
+    // Positions of the other segment's ends along this one, where 0 and 1 are its own ends
+    let along = |(x, z): (f64, f64)| ((x - ax) * dx + (z - az) * dz) / length_squared;
+    let (start, end) = (along(b.0), along(b.1));
+    start.max(end).min(1.0) - start.min(end).max(0.0) > EPSILON
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, z: f64, size: f64) -> Polygon {
+        Polygon::from_xz(&[x, x + size, x + size, x], &[z, z, z + size, z + size])
+    }
+
+    /// A 48x48 U opening upwards, whose centroid falls in the gap between its arms.
+    fn u_shape() -> Polygon {
+        Polygon::from_xz(
+            &[0.0, 48.0, 48.0, 32.0, 32.0, 16.0, 16.0, 0.0],
+            &[0.0, 0.0, 48.0, 48.0, 16.0, 16.0, 48.0, 48.0],
+        )
+    }
+
+    #[test]
+    fn measures_area_and_bounding_box() {
+        let square = square(16.0, -32.0, 16.0);
+        assert_eq!(square.area(), 256.0);
+        assert_eq!(square.bounding_box(), Some(BoundingBox { min_x: 16.0, min_z: -32.0, max_x: 32.0, max_z: -16.0 }));
+        // Winding order does not change the area
+        let mut clockwise = square.clone();
+        clockwise.vertices.reverse();
+        assert_eq!(clockwise.area(), 256.0);
+
+        assert_eq!(u_shape().area(), 48.0 * 48.0 - 16.0 * 32.0);
+        assert_eq!(Polygon::from_xz(&[0.0, 16.0], &[0.0, 16.0]).area(), 0.0);
+        assert_eq!(Polygon::default().bounding_box(), None);
+    }
+
+    #[test]
+    fn tests_points_inside() {
+        let u = u_shape();
+        assert!(u.contains((8.0, 40.0)));
+        assert!(u.contains((24.0, 8.0)));
+        assert!(!u.contains((24.0, 32.0)));
+        assert!(!u.contains((64.0, 8.0)));
+    }
+
+    #[test]
+    fn representative_point_lies_inside_concave_claims() {
+        let u = u_shape();
+        let point = u.representative_point().unwrap();
+        assert!(u.contains(point));
+        assert_eq!(point.1, 32.0);
+
+        let square = square(0.0, 0.0, 16.0);
+        assert_eq!(square.representative_point(), Some((8.0, 8.0)));
+        assert_eq!(Polygon::from_xz(&[0.0, 16.0, 32.0], &[0.0, 0.0, 0.0]).representative_point(), None);
+    }
+
+    #[test]
+    fn parts_sharing_edges_are_not_outposts() {
+        // Dynmap split the main claim in two along x = 16; a third piece hangs off the second
+        let claim = MultiPolygon {
+            parts: vec![square(0.0, 0.0, 16.0), square(16.0, 0.0, 16.0), square(16.0, 16.0, 32.0)],
+        };
+        assert_eq!(claim.outposts(0).count(), 0);
+
+        // Touching only at a corner does not join two parts
+        let claim = MultiPolygon { parts: vec![square(0.0, 0.0, 16.0), square(16.0, 16.0, 16.0)] };
+        assert_eq!(claim.outposts(0).collect::<Vec<_>>(), [&square(16.0, 16.0, 16.0)]);
+    }
+
+    #[test]
+    fn finds_outposts_inside_the_main_bounding_box() {
+        // An outpost in the gap between the arms of the U, within its bounding box
+        let outpost = square(20.0, 36.0, 8.0);
+        let claim = MultiPolygon { parts: vec![u_shape(), outpost.clone()] };
+        assert_eq!(claim.main_part(None), Some(0));
+        assert_eq!(claim.outposts(0).collect::<Vec<_>>(), [&outpost]);
+    }
+
+    #[test]
+    fn every_other_part_of_a_disjoint_main_claim_is_an_outpost() {
+        // The home block is in a small part far from the larger ones
+        let claim = MultiPolygon {
+            parts: vec![square(0.0, 0.0, 64.0), square(64.0, 0.0, 16.0), square(512.0, 512.0, 16.0)],
+        };
+        let main = claim.main_part(Some((520.0, 520.0))).unwrap();
+        assert_eq!(main, 2);
+        assert_eq!(claim.outposts(main).count(), 2);
+        assert_eq!(claim.outposts(0).collect::<Vec<_>>(), [&square(512.0, 512.0, 16.0)]);
+    }
+}
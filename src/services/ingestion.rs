@@ -0,0 +1,199 @@
+// Filename: services/ingestion.rs
+// Supervises the per-source data fetchers and tracks their health
+
+use crate::config::secret::{HistoryConfig, SourceConfig};
+use crate::services::archive::SnapshotArchive;
+use crate::services::data;
+use crate::services::leaderboard::TownCache;
+use crate::services::settings::SettingsCache;
+use crate::services::watch::{Notifier, WatchCache};
+use aws_sdk_dynamodb::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+/// Delay before restarting a crashed fetcher; doubled per consecutive crash.
+const BASE_RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Upper bound on the restart delay.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+
+/// A fetcher that survives this long is considered healthy again, resetting the restart delay.
+const STABLE_RUNTIME: Duration = Duration::from_secs(600);
+
+/// Health of one source's ingestion, as seen by commands.
+#[derive(Debug, Clone, Default)]
+pub struct IngestionHealth {
+    /// Unix timestamp of the last successfully processed cycle
+    pub last_success: Option<i64>,
+    pub last_error: Option<String>,
+    /// Unix timestamp of `last_error`
+    pub last_error_at: Option<i64>,
+    pub consecutive_failures: u32,
+    pub last_cycle_duration: Option<Duration>,
+    /// Number of times the fetcher task was restarted after returning or panicking
+    pub restarts: u32,
+}
+
+/// Shared registry of every source's ingestion health, keyed by source name.
+#[derive(Debug, Clone, Default)]
+pub struct IngestionMonitor {
+    health: Arc<RwLock<HashMap<String, IngestionHealth>>>,
+}
+
+impl IngestionMonitor {
+    pub fn snapshot(&self) -> HashMap<String, IngestionHealth> {
+        self.health.read().map(|health| health.clone()).unwrap_or_default()
+    }
+
+    fn update(&self, source: &str, f: impl FnOnce(&mut IngestionHealth)) {
+        if let Ok(mut health) = self.health.write() {
+            f(health.entry(source.to_owned()).or_default());
+        }
+    }
+
+    pub fn record_success(&self, source: &str, duration: Duration) {
+        self.update(source, |health| {
+            health.last_success = Some(unix_now());
+            health.consecutive_failures = 0;
+            health.last_cycle_duration = Some(duration);
+        });
+    }
+
+    /// Records a failed cycle and returns the number of consecutive failures.
+    pub fn record_failure(&self, source: &str, error: &str, duration: Duration) -> u32 {
+        let mut failures = 0;
+        self.update(source, |health| {
+            health.last_error = Some(error.to_owned());
+            health.last_error_at = Some(unix_now());
+            health.consecutive_failures += 1;
+            health.last_cycle_duration = Some(duration);
+            failures = health.consecutive_failures;
+        });
+        failures
+    }
+
+    fn record_restart(&self, source: &str, reason: &str) {
+        self.update(source, |health| {
+            health.last_error = Some(reason.to_owned());
+            health.last_error_at = Some(unix_now());
+            health.restarts += 1;
+        });
+    }
+}
+
+/// Starts a supervised fetcher for `source`. The fetcher is restarted with
+/// exponential backoff whenever it returns or panics.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn(
+    source: SourceConfig,
+    db_client: Client,
+    monitor: IngestionMonitor,
+    archive: Option<SnapshotArchive>,
+    history: HistoryConfig,
+    cache: TownCache,
+    watches: WatchCache,
+    settings: SettingsCache,
+    notifier: Notifier,
+) {
+    tokio::spawn(async move {
+        let mut crashes = 0u32;
+        loop {
+            let started = Instant::now();
+            let task_source = source.clone();
+            let task_client = db_client.clone();
+            let task_monitor = monitor.clone();
+            let task_archive = archive.clone();
+            let task_history = history.clone();
+            let task_cache = cache.clone();
+            let task_watches = watches.clone();
+            let task_settings = settings.clone();
+            let task_notifier = notifier.clone();
+            let handle = tokio::spawn(async move {
+                data::fetch_data(&task_source, &task_client, &task_monitor, task_archive.as_ref(), &task_history, &task_cache, &task_watches, &task_settings, &task_notifier)
+                    .await
+                    .map_err(|e| e.to_string())
+            });
+
+            let reason = match handle.await {
+                Ok(Ok(())) => "fetcher exited".to_owned(),
+                Ok(Err(e)) => format!("fetcher failed: {}", e),
+                Err(e) if e.is_panic() => format!("fetcher panicked: {}", panic_message(e.into_panic())),
+                Err(e) => format!("fetcher was cancelled: {}", e),
+            };
+
+            if started.elapsed() >= STABLE_RUNTIME {
+                crashes = 0;
+            }
+            let delay = backoff(BASE_RESTART_DELAY, crashes, MAX_RESTART_DELAY);
+            crashes = crashes.saturating_add(1);
+
+            println!("[{}] Ingestion stopped ({}); restarting in {}s", source.name, reason, delay.as_secs());
+            monitor.record_restart(&source.name, &reason);
+            sleep(delay).await;
+        }
+    });
+}
+
+/// `base` doubled `doublings` times, capped at `max`.
+pub fn backoff(base: Duration, doublings: u32, max: Duration) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(doublings.min(16))).min(max)
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_consecutive_failures_until_a_success() {
+        let monitor = IngestionMonitor::default();
+        assert_eq!(monitor.record_failure("earth", "timed out", Duration::from_secs(1)), 1);
+        assert_eq!(monitor.record_failure("earth", "HTTP 502", Duration::from_secs(2)), 2);
+        assert_eq!(monitor.record_failure("mars", "timed out", Duration::from_secs(1)), 1);
+
+        let health = &monitor.snapshot()["earth"];
+        assert_eq!(health.consecutive_failures, 2);
+        assert_eq!(health.last_error.as_deref(), Some("HTTP 502"));
+        assert!(health.last_error_at.is_some());
+        assert_eq!(health.last_success, None);
+
+        monitor.record_success("earth", Duration::from_secs(3));
+        let health = &monitor.snapshot()["earth"];
+        assert_eq!(health.consecutive_failures, 0);
+        assert!(health.last_success.is_some());
+        assert_eq!(health.last_cycle_duration, Some(Duration::from_secs(3)));
+        // The last error stays visible after recovering
+        assert_eq!(health.last_error.as_deref(), Some("HTTP 502"));
+        assert_eq!(monitor.record_failure("earth", "timed out", Duration::from_secs(1)), 1);
+
+        monitor.record_restart("mars", "fetcher panicked: boom");
+        let health = &monitor.snapshot()["mars"];
+        assert_eq!(health.restarts, 1);
+        assert_eq!(health.last_error.as_deref(), Some("fetcher panicked: boom"));
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let delays: Vec<u64> = (0..8).map(|crashes| backoff(BASE_RESTART_DELAY, crashes, MAX_RESTART_DELAY).as_secs()).collect();
+        assert_eq!(delays, [5, 10, 20, 40, 80, 160, 300, 300]);
+        // Long failure streaks neither overflow nor exceed the cap
+        assert_eq!(backoff(Duration::from_secs(60), u32::MAX, Duration::from_secs(900)), Duration::from_secs(900));
+    }
+}
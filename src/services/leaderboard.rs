@@ -0,0 +1,323 @@
+// Filename: services/leaderboard.rs
+// In-memory town rankings, refreshed by ingestion and compared against the previous day
+
+use crate::models::history::TownSnapshot;
+use crate::models::towns::Town;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+
+/// Minimum time between two kept ranking samples.
+const SAMPLE_INTERVAL_SECS: i64 = 3600;
+
+/// Rank changes are measured against the newest sample at least this old.
+pub const BASELINE_AGE_SECS: i64 = 86_400;
+
+/// What towns can be ranked by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Metric {
+    #[name = "Richest"]
+    Bank,
+    #[name = "Most populous"]
+    Residents,
+    #[name = "Largest"]
+    Area,
+    #[name = "Oldest"]
+    Age,
+    #[name = "Highest upkeep"]
+    Upkeep,
+}
+
+impl Metric {
+    /// Sort key; higher ranks first.
+    fn score(&self, town: &RankSample) -> f64 {
+        match self {
+            Metric::Bank => town.bank,
+            Metric::Residents => town.residents as f64,
+            Metric::Area => town.area,
+            Metric::Age => -(town.founded as f64),
+            Metric::Upkeep => town.upkeep,
+        }
+    }
+
+    /// Whether the town has a value worth ranking; towns without a parsed
+    /// founding date cannot be ranked by age.
+    fn ranks(&self, town: &RankSample) -> bool {
+        *self != Metric::Age || town.founded > 0
+    }
+}
+
+/// The fields of a standing town that rankings are built from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankSample {
+    pub town_id: String,
+    pub town_name: String,
+    pub nation: Option<String>,
+    pub bank: f64,
+    pub residents: usize,
+    pub area: f64,
+    pub founded: i64,
+    pub upkeep: f64,
+}
+
+impl RankSample {
+    pub fn from_town(town: &Town) -> Self {
+        Self {
+            town_id: town.town_id.clone(),
+            town_name: town.town_name.clone(),
+            nation: town.nation.clone(),
+            bank: town.bank,
+            residents: town.residents.len(),
+            area: town.area,
+            founded: town.founded,
+            upkeep: town.upkeep,
+        }
+    }
+
+    /// The town as recorded in a history point, which does not hold the
+    /// founding date.
+    pub fn from_snapshot(snapshot: &TownSnapshot, founded: i64) -> Self {
+        Self {
+            town_id: snapshot.town_id.clone(),
+            town_name: snapshot.town_name.clone(),
+            nation: snapshot.nation.clone(),
+            bank: snapshot.bank,
+            residents: snapshot.residents,
+            area: snapshot.area,
+            founded,
+            upkeep: snapshot.upkeep,
+        }
+    }
+}
+
+/// One line of a leaderboard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedTown {
+    pub rank: usize,
+    pub town: RankSample,
+    /// Places gained since the previous day, or `None` if the town was not ranked then
+    pub change: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct SourceRankings {
+    current: Arc<Vec<RankSample>>,
+    /// Every standing town as last seen
+    standing: Arc<Vec<Town>>,
+    /// Names of every known town, fallen or standing, for lookups by name
+    names: Arc<Vec<String>>,
+    /// Samples kept for rank changes, oldest first, at most one per `SAMPLE_INTERVAL_SECS`
+    samples: VecDeque<(i64, Arc<Vec<RankSample>>)>,
+}
+
+/// Shared snapshot of every source's towns, keyed by source name.
+#[derive(Debug, Clone, Default)]
+pub struct TownCache {
+    sources: Arc<RwLock<HashMap<String, SourceRankings>>>,
+}
+
+impl TownCache {
+    /// Replaces the source's towns with `towns`, seen at `timestamp`. Only
+    /// standing towns are ranked, but every town's name is indexed.
+    pub fn update<'a>(&self, source: &str, towns: impl IntoIterator<Item = &'a Town>, timestamp: i64) {
+        let mut names = Vec::new();
+        let mut current = Vec::new();
+        let mut standing = Vec::new();
+        for town in towns {
+            names.push(town.town_name.clone());
+            if !town.is_fallen() {
+                current.push(RankSample::from_town(town));
+                standing.push(town.clone());
+            }
+        }
+        names.sort_unstable();
+        let current = Arc::new(current);
+        let Ok(mut sources) = self.sources.write() else {
+            return;
+        };
+        let rankings = sources.entry(source.to_owned()).or_default();
+        rankings.current = current.clone();
+        rankings.standing = Arc::new(standing);
+        rankings.names = Arc::new(names);
+        if rankings.samples.back().is_none_or(|(time, _)| timestamp - time >= SAMPLE_INTERVAL_SECS) {
+            rankings.samples.push_back((timestamp, current));
+        }
+        // Keep only the newest sample old enough to be the baseline, and everything after it
+        while rankings.samples.get(1).is_some_and(|(time, _)| timestamp - time >= BASELINE_AGE_SECS) {
+            rankings.samples.pop_front();
+        }
+    }
+
+    /// Adds `towns`, as they stood at `timestamp`, as the source's oldest
+    /// sample, so rank changes are known straight after a restart. Ignored
+    /// once the cache holds a sample at least as old.
+    pub fn seed_baseline(&self, source: &str, towns: Vec<RankSample>, timestamp: i64) {
+        let Ok(mut sources) = self.sources.write() else {
+            return;
+        };
+        let rankings = sources.entry(source.to_owned()).or_default();
+        if rankings.samples.front().is_none_or(|(time, _)| *time > timestamp) {
+            rankings.samples.push_front((timestamp, Arc::new(towns)));
+        }
+    }
+
+    /// Names of every town of the source, sorted; empty until ingestion has
+    /// filled the cache.
+    pub fn town_names(&self, source: &str) -> Arc<Vec<String>> {
+        self.sources
+            .read()
+            .ok()
+            .and_then(|sources| sources.get(source).map(|rankings| rankings.names.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Every standing town of the source. Returns `None` until ingestion has
+    /// filled the cache.
+    pub fn standing_towns(&self, source: &str) -> Option<Arc<Vec<Town>>> {
+        let sources = self.sources.read().ok()?;
+        sources.get(source).map(|rankings| rankings.standing.clone())
+    }
+
+    /// Ranks the source's towns by `metric`, optionally within one nation.
+    /// Returns `None` until ingestion has filled the cache.
+    pub fn leaderboard(&self, source: &str, metric: Metric, nation: Option<&str>, now: i64) -> Option<Vec<RankedTown>> {
+        let sources = self.sources.read().ok()?;
+        let rankings = sources.get(source)?;
+        let baseline = rankings
+            .samples
+            .iter()
+            .rev()
+            .find(|(time, _)| now - time >= BASELINE_AGE_SECS)
+            .map(|(_, towns)| towns.as_slice());
+        Some(leaderboard(&rankings.current, baseline, metric, nation))
+    }
+}
+
+/// Ranks `towns` by `metric`, optionally within one nation, with each town's
+/// rank change since `baseline`.
+pub fn leaderboard(towns: &[RankSample], baseline: Option<&[RankSample]>, metric: Metric, nation: Option<&str>) -> Vec<RankedTown> {
+    let previous: HashMap<&str, usize> = baseline
+        .map(|baseline| {
+            rank(baseline, metric, nation)
+                .into_iter()
+                .enumerate()
+                .map(|(i, town)| (town.town_id.as_str(), i + 1))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    rank(towns, metric, nation)
+        .into_iter()
+        .enumerate()
+        .map(|(i, town)| RankedTown {
+            rank: i + 1,
+            change: previous.get(town.town_id.as_str()).map(|&was| was as i64 - (i + 1) as i64),
+            town: town.clone(),
+        })
+        .collect()
+}
+
+fn rank<'a>(towns: &'a [RankSample], metric: Metric, nation: Option<&str>) -> Vec<&'a RankSample> {
+    let mut ranked: Vec<&RankSample> = towns
+        .iter()
+        .filter(|town| metric.ranks(town))
+        .filter(|town| nation.is_none_or(|wanted| town.nation.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(wanted))))
+        .collect();
+    ranked.sort_by(|a, b| {
+        metric.score(b).total_cmp(&metric.score(a)).then_with(|| a.town_name.cmp(&b.town_name))
+    });
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str, nation: Option<&str>, bank: f64, founded: i64) -> RankSample {
+        RankSample {
+            town_id: format!("{}@0", name.to_lowercase()),
+            town_name: name.to_owned(),
+            nation: nation.map(str::to_owned),
+            bank,
+            residents: 1,
+            area: 256.0,
+            founded,
+            upkeep: 10.0,
+        }
+    }
+
+    #[test]
+    fn ranks_with_changes_since_baseline() {
+        let yesterday = vec![
+            sample("Astarte", Some("Rome"), 500.0, 100),
+            sample("Lonely", None, 900.0, 300),
+            sample("Gaul", Some("Rome"), 100.0, 0),
+        ];
+        let today = vec![
+            sample("Astarte", Some("Rome"), 1000.0, 100),
+            sample("Lonely", None, 900.0, 300),
+            sample("Gaul", Some("Rome"), 100.0, 0),
+            sample("Upstart", Some("rome"), 50.0, 200),
+        ];
+
+        let board = leaderboard(&today, Some(&yesterday), Metric::Bank, None);
+        let lines: Vec<_> = board.iter().map(|t| (t.rank, t.town.town_name.as_str(), t.change)).collect();
+        assert_eq!(lines, [
+            (1, "Astarte", Some(1)),
+            (2, "Lonely", Some(-1)),
+            (3, "Gaul", Some(0)),
+            (4, "Upstart", None),
+        ]);
+
+        // Nation filters are case-insensitive and rank within the nation
+        let rome: Vec<_> = leaderboard(&today, Some(&yesterday), Metric::Bank, Some("ROME"))
+            .into_iter()
+            .map(|t| (t.town.town_name, t.change))
+            .collect();
+        assert_eq!(rome, [("Astarte".to_owned(), Some(0)), ("Gaul".to_owned(), Some(0)), ("Upstart".to_owned(), None)]);
+
+        // Oldest first, skipping towns without a founding date
+        let oldest: Vec<_> = leaderboard(&today, None, Metric::Age, None).into_iter().map(|t| t.town.town_name).collect();
+        assert_eq!(oldest, ["Astarte", "Upstart", "Lonely"]);
+    }
+
+    #[test]
+    fn cache_compares_against_a_day_old_sample() {
+        let cache = TownCache::default();
+        let towns = |astarte: f64, lonely: f64| {
+            let mut towns = [Town::test_town("Astarte"), Town::test_town("Lonely")];
+            towns[0].bank = astarte;
+            towns[1].bank = lonely;
+            towns
+        };
+        cache.update("test", &towns(100.0, 200.0), 0);
+        cache.update("test", &towns(300.0, 200.0), 1800);
+
+        // Less than a day in: no baseline yet
+        let board = cache.leaderboard("test", Metric::Bank, None, 7200).unwrap();
+        assert_eq!((board[0].town.town_name.as_str(), board[0].change), ("Astarte", None));
+
+        // A day after the first sample, which the half-hour-later update did not replace
+        cache.update("test", &towns(300.0, 200.0), 86_400);
+        let board = cache.leaderboard("test", Metric::Bank, None, 86_400).unwrap();
+        assert_eq!((board[0].town.town_name.as_str(), board[0].change), ("Astarte", Some(1)));
+        assert!(cache.leaderboard("other", Metric::Bank, None, 0).is_none());
+        assert_eq!(*cache.town_names("test"), ["Astarte", "Lonely"]);
+    }
+
+    #[test]
+    fn seeded_baseline_gives_rank_changes_after_a_restart() {
+        let cache = TownCache::default();
+        let mut towns = [Town::test_town("Astarte"), Town::test_town("Lonely")];
+        towns[0].bank = 300.0;
+        towns[1].bank = 200.0;
+        let now = 100_000;
+        cache.update("test", &towns, now);
+
+        let mut yesterday = TownSnapshot::from_town(&towns[1]);
+        yesterday.bank = 500.0;
+        cache.seed_baseline("test", vec![RankSample::from_snapshot(&yesterday, 0)], now - BASELINE_AGE_SECS);
+        let board = cache.leaderboard("test", Metric::Bank, None, now).unwrap();
+        let lines: Vec<_> = board.iter().map(|t| (t.town.town_name.as_str(), t.change)).collect();
+        assert_eq!(lines, [("Astarte", None), ("Lonely", Some(-1))]);
+    }
+}
@@ -0,0 +1,93 @@
+// Filename: services/lineage.rs
+// Heuristics linking towns that vanished in a cycle to the towns that carried them on
+
+use crate::models::towns::Town;
+use std::collections::HashSet;
+
+/// Minimum similarity for a vanished town and a new town to count as a rename.
+const RENAME_THRESHOLD: f64 = 0.6;
+
+/// Share of a vanished town's residents that must live in another town for a merge.
+const MERGE_RESIDENT_SHARE: f64 = 0.5;
+
+/// A vanished town and the index of the town that carried it on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    pub vanished: usize,
+    pub successor: usize,
+    pub confidence: f64,
+}
+
+/// Similarity between a vanished town and a newly seen one, from 0.0 to 1.0.
+/// Founded date, mayor, resident overlap and claim overlap weigh equally.
+/// Claims are compared by bounding box, the only claim shape stored per row.
+pub fn rename_score(old: &Town, new: &Town) -> f64 {
+    let founded = if old.founded != 0 && old.founded == new.founded { 1.0 } else { 0.0 };
+    let mayor = if !old.mayor.is_empty() && old.mayor.eq_ignore_ascii_case(&new.mayor) { 1.0 } else { 0.0 };
+    let residents = jaccard(&old.residents, &new.residents);
+    let claims = old.bounding_box().overlap(&new.bounding_box());
+    (founded + mayor + residents + claims) / 4.0
+}
+
+/// Pairs vanished towns with new towns one to one, best matches first.
+pub fn find_renames(vanished: &[&Town], new: &[&Town]) -> Vec<Link> {
+    let mut candidates: Vec<Link> = Vec::new();
+    for (i, old) in vanished.iter().enumerate() {
+        for (j, town) in new.iter().enumerate() {
+            let confidence = rename_score(old, town);
+            if confidence >= RENAME_THRESHOLD {
+                candidates.push(Link { vanished: i, successor: j, confidence });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    let mut used_old = HashSet::new();
+    let mut used_new = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|link| {
+            if used_old.contains(&link.vanished) || used_new.contains(&link.successor) {
+                return false;
+            }
+            used_old.insert(link.vanished);
+            used_new.insert(link.successor);
+            true
+        })
+        .collect()
+}
+
+/// Finds the surviving town each vanished town was merged into: the one holding
+/// the largest share of its residents, provided that share is large enough and
+/// the two claims touch. Several towns may merge into the same survivor.
+pub fn find_merges(vanished: &[&Town], survivors: &[&Town]) -> Vec<Link> {
+    vanished
+        .iter()
+        .enumerate()
+        .filter(|(_, old)| !old.residents.is_empty())
+        .filter_map(|(i, old)| {
+            survivors
+                .iter()
+                .enumerate()
+                .filter(|(_, town)| old.bounding_box().touches(&town.bounding_box()))
+                .map(|(j, town)| {
+                    let moved = old.residents.iter().filter(|r| town.residents.contains(r)).count();
+                    (j, moved as f64 / old.residents.len() as f64)
+                })
+                .filter(|&(_, share)| share >= MERGE_RESIDENT_SHARE)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(j, share)| Link { vanished: i, successor: j, confidence: share })
+        })
+        .collect()
+}
+
+/// Size of the intersection over size of the union, case-insensitively.
+fn jaccard(a: &[String], b: &[String]) -> f64 {
+    let a: HashSet<String> = a.iter().map(|s| s.to_lowercase()).collect();
+    let b: HashSet<String> = b.iter().map(|s| s.to_lowercase()).collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
@@ -0,0 +1,38 @@
+// Filename: services/locale.rs
+// Writing amounts of money the way a server's chosen locale does
+
+/// Writes `amount` as dollars with the digit grouping and decimal mark of `locale`.
+pub fn format_money(amount: f64, locale: Option<&str>) -> String {
+    let (group, decimal) = match locale {
+        Some("es-419") | None => (",", "."),
+        Some(locale) => match locale.split('-').next().unwrap_or(locale) {
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "da" | "tr" | "el" | "ro" | "hr" | "vi" => (".", ","),
+            "fr" | "ru" | "uk" | "cs" | "sv" | "fi" | "no" | "pl" | "bg" | "hu" | "lt" => ("\u{a0}", ","),
+            _ => (",", "."),
+        },
+    };
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let digits = (cents / 100).to_string();
+    let mut whole = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            whole.push_str(group);
+        }
+        whole.push(digit);
+    }
+    let sign = if amount < 0.0 && cents > 0 { "-" } else { "" };
+    format!("{}${}{}{:02}", sign, whole, decimal, cents % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_money_for_the_locale() {
+        assert_eq!(format_money(1234567.891, None), "$1,234,567.89");
+        assert_eq!(format_money(1234.5, Some("de")), "$1.234,50");
+        assert_eq!(format_money(-12.0, Some("fr")), "-$12,00");
+        assert_eq!(format_money(999.0, Some("es-419")), "$999.00");
+    }
+}
@@ -1,5 +1,22 @@
 // Filename: servaices/mod.rs
 // Entry point for services modules
 
+pub mod archive;
 pub mod data;
-pub mod town;
\ No newline at end of file
+pub mod digest;
+pub mod drift;
+pub mod forecast;
+pub mod geometry;
+pub mod ingestion;
+pub mod leaderboard;
+pub mod lineage;
+pub mod locale;
+pub mod nation;
+pub mod parser;
+pub mod player;
+pub mod replay;
+pub mod search;
+pub mod settings;
+pub mod town;
+pub mod upkeep;
+pub mod watch;
\ No newline at end of file
@@ -0,0 +1,76 @@
+// Filename: services/nation.rs
+// Aggregates towns into nations and serves nation lookups
+
+use crate::config::secret::SourceConfig;
+use crate::models::nations::Nation;
+use crate::models::towns::Town;
+use crate::repositories::nations::NationRepository;
+use crate::services::upkeep::fall_time;
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use chrono::DateTime;
+use std::collections::BTreeMap;
+
+pub struct NationService<'a> {
+    repository: NationRepository<'a>,
+}
+
+impl<'a> NationService<'a> {
+    pub fn new(client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            repository: NationRepository::new(client, source),
+        }
+    }
+
+    pub async fn get_nation_info(&self, nation_name: &str) -> Result<Option<Nation>, Error> {
+        self.repository.get_nation(nation_name).await
+    }
+}
+
+/// Groups every standing town by nation and sums up each nation as of `timestamp`.
+/// The capital is the town Dynmap marks as one, or failing that the town with
+/// the most residents.
+pub fn aggregate_nations<'a>(towns: impl IntoIterator<Item = &'a Town>, timestamp: i64) -> Vec<Nation> {
+    let now = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+    let mut members: BTreeMap<String, Vec<&Town>> = BTreeMap::new();
+    for town in towns {
+        if let (Some(nation), false) = (&town.nation, town.is_fallen()) {
+            members.entry(nation.to_lowercase()).or_default().push(town);
+        }
+    }
+
+    members
+        .into_iter()
+        .map(|(nation_name_lower, mut towns)| {
+            towns.sort_by(|a, b| a.town_name.cmp(&b.town_name));
+            let capital = towns
+                .iter()
+                .find(|town| town.capital)
+                .or_else(|| towns.iter().max_by_key(|town| town.residents.len()))
+                .copied();
+            let next_fall = towns
+                .iter()
+                .filter_map(|town| Some((fall_time(town.bank, town.upkeep, now)?, town)))
+                .min_by_key(|(falls, _)| *falls);
+
+            let mut nation = Nation {
+                nation_name: capital.and_then(|town| town.nation.clone()).unwrap_or_default(),
+                nation_name_lower,
+                capital: capital.map(|town| town.town_name.clone()),
+                leader: capital.map(|town| town.mayor.clone()),
+                towns: towns.iter().map(|town| town.town_name.clone()).collect(),
+                residents: towns.iter().map(|town| town.residents.len()).sum(),
+                bank: towns.iter().map(|town| town.bank).sum(),
+                area: towns.iter().map(|town| town.area).sum(),
+                upkeep: towns.iter().map(|town| town.upkeep).sum(),
+                next_fall: next_fall.map(|(falls, _)| falls.timestamp()),
+                next_falling_town: next_fall.map(|(_, town)| town.town_name.clone()),
+                content_hash: String::new(),
+                last_updated: timestamp,
+                disbanded_at: None,
+            };
+            nation.content_hash = nation.compute_content_hash();
+            nation
+        })
+        .collect()
+}
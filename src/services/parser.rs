@@ -0,0 +1,260 @@
+// Filename: services/parser.rs
+// Single-pass parser for the Towny HTML description Dynmap attaches to each town
+
+use htmlentity::entity::{decode, ICodedDataTrait};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Matches every labelled span in a description together with the plain text
+/// that follows it, e.g. `<span style="font-weight:bold">Bank</span>: $1,000<br`.
+/// Values never contain a raw `<` because Towny entity-encodes them.
+static SPAN_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<span style="(font-weight:bold|font-size:150%)">(.*?)</span>\s*:?\s*([^<]*)"#)
+        .expect("Invalid description pattern")
+});
+
+/// Strips nested tags out of a span label, e.g. emoji wrapped in `<img>`.
+static TAG_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<[^>]*>").expect("Invalid tag pattern")
+});
+
+/// Everything we read out of a town's description. A field is `None` when its
+/// label was not present at all, which is distinct from an empty value.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TownDescription {
+    pub nation: Option<String>,
+    pub mayor: Option<String>,
+    pub peaceful: Option<bool>,
+    pub culture: Option<String>,
+    pub board: Option<String>,
+    pub bank: Option<f64>,
+    pub upkeep: Option<f64>,
+    pub founded: Option<i64>,
+    pub resources: Option<Vec<String>>,
+    pub residents: Option<Vec<String>>,
+    pub trusted_players: Option<Vec<String>>,
+}
+
+impl TownDescription {
+    /// Parses a description in a single scan over its labelled spans.
+    pub fn parse(desc: &str) -> Self {
+        let mut parsed = TownDescription::default();
+
+        for caps in SPAN_PATTERN.captures_iter(desc) {
+            let style = &caps[1];
+            let label = TAG_PATTERN.replace_all(&caps[2], "");
+            let label = label.trim();
+            let value = decode_entities(caps[3].trim());
+
+            if style == "font-size:150%" {
+                if let Some(nation) = label.strip_prefix("Member of ") {
+                    parsed.nation.get_or_insert_with(|| decode_entities(nation.trim()));
+                }
+                continue;
+            }
+
+            // Labels may carry a decorative prefix, so match on how they end
+            if label.ends_with("Mayor") {
+                parsed.mayor.get_or_insert(value);
+            } else if label.ends_with("Peaceful?") {
+                parsed.peaceful.get_or_insert(value.eq_ignore_ascii_case("true"));
+            } else if label.ends_with("Culture") {
+                parsed.culture.get_or_insert(value);
+            } else if label.ends_with("Board") {
+                parsed.board.get_or_insert(value);
+            } else if label.ends_with("Bank") {
+                parsed.bank = parsed.bank.or_else(|| parse_currency(&value));
+            } else if label.ends_with("Upkeep") {
+                parsed.upkeep = parsed.upkeep.or_else(|| parse_currency(&value));
+            } else if label.ends_with("Founded") {
+                parsed.founded = parsed.founded.or_else(|| parse_date(&value));
+            } else if label.ends_with("Resources") {
+                parsed.resources.get_or_insert_with(|| split_list(&value));
+            } else if label.ends_with("Trusted Players") {
+                parsed.trusted_players.get_or_insert_with(|| split_list(&value));
+            } else if is_residents_label(label) {
+                parsed.residents.get_or_insert_with(|| split_list(&value));
+            }
+        }
+
+        parsed
+    }
+
+    /// Every field name paired with whether it was found, in template order.
+    pub fn field_presence(&self) -> [(&'static str, bool); 11] {
+        [
+            ("nation", self.nation.is_some()),
+            ("mayor", self.mayor.is_some()),
+            ("peaceful", self.peaceful.is_some()),
+            ("culture", self.culture.is_some()),
+            ("board", self.board.is_some()),
+            ("bank", self.bank.is_some()),
+            ("upkeep", self.upkeep.is_some()),
+            ("founded", self.founded.is_some()),
+            ("resources", self.resources.is_some()),
+            ("residents", self.residents.is_some()),
+            ("trusted_players", self.trusted_players.is_some()),
+        ]
+    }
+}
+
+/// Matches `Residents (12)`, with or without a decorative prefix.
+fn is_residents_label(label: &str) -> bool {
+    label
+        .rsplit_once("Residents")
+        .is_some_and(|(_, count)| {
+            let count = count.trim();
+            count.starts_with('(') && count.ends_with(')')
+        })
+}
+
+fn decode_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_owned();
+    }
+    decode(value.as_bytes())
+        .to_string()
+        .unwrap_or_else(|_| value.to_owned())
+}
+
+/// Splits a comma-separated list, dropping empty entries.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Parses a currency string such as `$1,234.50`, or `-$12.00` for a town in debt.
+pub fn parse_currency(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let amount: f64 = value.trim_start_matches('$').replace(',', "").parse().ok()?;
+    Some(if negative { -amount } else { amount })
+}
+
+/// Parses a founding date such as `Dec 1 2024` into a Unix timestamp at midnight UTC.
+pub fn parse_date(date: &str) -> Option<i64> {
+    chrono::NaiveDate::parse_from_str(date.trim(), "%b %d %Y")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn fixtures_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/descriptions")
+    }
+
+    /// Every `<case>.html` in the fixtures directory must parse to `<case>.json`.
+    /// Run with `UPDATE_GOLDEN=1` to rewrite the expected files after an intended change.
+    #[test]
+    fn golden_corpus() {
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut checked = 0;
+        let mut failures = Vec::new();
+
+        for entry in fs::read_dir(fixtures_dir()).expect("Missing fixtures directory") {
+            let path = entry.expect("Unreadable fixture").path();
+            if path.extension().is_none_or(|ext| ext != "html") {
+                continue;
+            }
+            let desc = fs::read_to_string(&path).expect("Unreadable fixture");
+            let expected_path = path.with_extension("json");
+            let actual = TownDescription::parse(&desc);
+            checked += 1;
+
+            if update {
+                let json = serde_json::to_string_pretty(&actual).expect("Unserializable description");
+                fs::write(&expected_path, json + "\n").expect("Unwritable fixture");
+                continue;
+            }
+
+            let expected: TownDescription = serde_json::from_str(
+                &fs::read_to_string(&expected_path).expect("Missing expected output"),
+            )
+            .expect("Malformed expected output");
+            if actual != expected {
+                failures.push(format!("{}:\n  expected {:?}\n  actual   {:?}", path.display(), expected, actual));
+            }
+        }
+
+        assert!(checked > 0, "No fixtures found in {}", fixtures_dir().display());
+        assert!(failures.is_empty(), "{} of {} fixtures failed:\n{}", failures.len(), checked, failures.join("\n"));
+    }
+
+    fn format_currency(cents: i64) -> String {
+        let sign = if cents < 0 { "-" } else { "" };
+        let cents = cents.unsigned_abs();
+        let digits = (cents / 100).to_string();
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        format!("{}${}.{:02}", sign, grouped, cents % 100)
+    }
+
+    fn template(fields: &[(&str, String)]) -> String {
+        fields
+            .iter()
+            .map(|(label, value)| format!(r#"<span style="font-weight:bold">{}</span>: {}<br />"#, label, value))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics(desc in ".*") {
+            let _ = TownDescription::parse(&desc);
+        }
+
+        #[test]
+        fn currency_round_trips(cents in -1_000_000_000i64..100_000_000_000) {
+            let parsed = parse_currency(&format_currency(cents));
+            prop_assert_eq!(parsed, Some(cents as f64 / 100.0));
+        }
+
+        #[test]
+        fn dates_round_trip(days in 0i64..40_000) {
+            let date = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap() + chrono::Duration::days(days);
+            let formatted = date.format("%b %-d %Y").to_string();
+            prop_assert_eq!(parse_date(&formatted), Some(days * 86_400));
+        }
+
+        #[test]
+        fn resident_lists_round_trip(names in prop::collection::vec("[A-Za-z0-9_]{3,16}", 0..60)) {
+            let desc = template(&[(&format!("Residents ({})", names.len()), names.join(", "))]);
+            prop_assert_eq!(TownDescription::parse(&desc).residents, Some(names));
+        }
+
+        #[test]
+        fn field_order_does_not_matter(bank in 0i64..1_000_000, mayor in "[A-Za-z0-9_]{3,16}", reverse in any::<bool>()) {
+            let mut fields = vec![
+                ("Mayor", mayor.clone()),
+                ("Bank", format_currency(bank)),
+                ("Culture", "Roman".to_owned()),
+            ];
+            if reverse {
+                fields.reverse();
+            }
+            let parsed = TownDescription::parse(&template(&fields));
+            prop_assert_eq!(parsed.mayor, Some(mayor));
+            prop_assert_eq!(parsed.bank, Some(bank as f64 / 100.0));
+            prop_assert_eq!(parsed.culture, Some("Roman".to_owned()));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Filename: services/player.rs
+// Builds the player index from towns and serves player lookups
+
+use crate::config::secret::SourceConfig;
+use crate::models::nations::Nation;
+use crate::models::players::{Player, PlayerRole};
+use crate::models::towns::Town;
+use crate::repositories::players::PlayerRepository;
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use std::collections::{BTreeMap, HashSet};
+
+pub struct PlayerService<'a> {
+    repository: PlayerRepository<'a>,
+}
+
+impl<'a> PlayerService<'a> {
+    pub fn new(client: &'a Client, source: &SourceConfig) -> Self {
+        Self {
+            repository: PlayerRepository::new(client, source),
+        }
+    }
+
+    pub async fn get_player_info(&self, player_name: &str) -> Result<Option<Player>, Error> {
+        self.repository.get_player(player_name).await
+    }
+}
+
+/// Indexes every resident and trusted player of the standing towns as of
+/// `timestamp`. Mayors of a nation's capital are indexed as its leader.
+/// Players only trusted in towns are indexed without a town of their own.
+pub fn index_players<'a>(
+    towns: impl IntoIterator<Item = &'a Town>,
+    nations: impl IntoIterator<Item = &'a Nation>,
+    timestamp: i64,
+) -> Vec<Player> {
+    let leaders: HashSet<String> = nations
+        .into_iter()
+        .filter(|nation| !nation.is_disbanded())
+        .filter_map(|nation| nation.leader.as_ref().map(|leader| leader.to_lowercase()))
+        .collect();
+    let mut towns: Vec<&Town> = towns.into_iter().filter(|town| !town.is_fallen()).collect();
+    towns.sort_by(|a, b| a.town_name.cmp(&b.town_name));
+
+    let mut players: BTreeMap<String, Player> = BTreeMap::new();
+    for town in &towns {
+        // The mayor lives in the town even when the map leaves them out of its residents
+        let mayor_unlisted = !town.mayor.is_empty()
+            && !town.residents.iter().any(|resident| resident.eq_ignore_ascii_case(&town.mayor));
+        let residents = town.residents.iter().chain(mayor_unlisted.then_some(&town.mayor));
+        for resident in residents {
+            let player = players.entry(resident.to_lowercase()).or_insert_with(|| new_player(resident, timestamp));
+            // A player can only live in one town; keep the first if the map disagrees
+            if player.town.is_some() {
+                continue;
+            }
+            let is_mayor = resident.eq_ignore_ascii_case(&town.mayor);
+            player.town = Some(town.town_name.clone());
+            player.nation = town.nation.clone();
+            player.role = Some(match (is_mayor, leaders.contains(&resident.to_lowercase())) {
+                (true, true) => PlayerRole::Leader,
+                (true, false) => PlayerRole::Mayor,
+                _ => PlayerRole::Resident,
+            });
+        }
+        for trusted in &town.trusted_players {
+            players
+                .entry(trusted.to_lowercase())
+                .or_insert_with(|| new_player(trusted, timestamp))
+                .trusted_in
+                .push(town.town_name.clone());
+        }
+    }
+
+    players
+        .into_values()
+        .map(|mut player| {
+            player.content_hash = player.compute_content_hash();
+            player
+        })
+        .collect()
+}
+
+fn new_player(name: &str, timestamp: i64) -> Player {
+    Player {
+        player_name: name.to_owned(),
+        player_name_lower: name.to_lowercase(),
+        town: None,
+        nation: None,
+        role: None,
+        trusted_in: Vec::new(),
+        content_hash: String::new(),
+        last_updated: timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_unlisted_mayors_and_trusted_only_players() {
+        let mut rome = Town::test_town("Rome");
+        rome.nation = Some("Roma".to_owned());
+        rome.mayor = "Caesar".to_owned();
+        rome.residents = vec!["Brutus".to_owned()];
+        rome.trusted_players = vec!["Cleopatra".to_owned(), "brutus".to_owned()];
+
+        let players = index_players([&rome], [], 100);
+        let summary: Vec<_> = players
+            .iter()
+            .map(|p| (p.player_name.as_str(), p.town.as_deref(), p.role, p.trusted_in.clone()))
+            .collect();
+        assert_eq!(summary, [
+            ("Brutus", Some("Rome"), Some(PlayerRole::Resident), vec!["Rome".to_owned()]),
+            ("Caesar", Some("Rome"), Some(PlayerRole::Mayor), vec![]),
+            ("Cleopatra", None, None, vec!["Rome".to_owned()]),
+        ]);
+        // Trust alone keeps a player in the index
+        assert!(!players[2].is_unaffiliated());
+        assert_eq!(players[1].nation.as_deref(), Some("Roma"));
+    }
+}
@@ -0,0 +1,197 @@
+// Filename: services/replay.rs
+// Offline ingestion of marker files from disk, for backfills and reproducing parser bugs
+
+use crate::config::secret::{HistoryConfig, SourceConfig};
+use crate::models::dynmap::MarkerFile;
+use crate::services::archive::{snapshot_time, SNAPSHOT_EXTENSION};
+use crate::services::data::{self, SourceState};
+use aws_sdk_dynamodb::Client;
+use flate2::read::GzDecoder;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// A marker file on disk, as written by the snapshot archive or saved by hand.
+#[derive(Debug, Clone)]
+pub struct SnapshotFile {
+    pub path: PathBuf,
+    /// Fetch time from the file name, if it follows the archive's naming scheme
+    pub named_time: Option<i64>,
+}
+
+/// Lists the marker files at `path`: the file itself, or every `.json` and
+/// `.json.gz` file in the directory, oldest first.
+pub fn list_snapshots(path: &Path) -> std::io::Result<Vec<SnapshotFile>> {
+    if path.is_file() {
+        return Ok(vec![snapshot_file(path.to_path_buf())]);
+    }
+
+    let mut files: Vec<SnapshotFile> = std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            path.is_file() && (name.ends_with(".json") || name.ends_with(SNAPSHOT_EXTENSION))
+        })
+        .map(snapshot_file)
+        .collect();
+    files.sort_by(|a, b| a.named_time.cmp(&b.named_time).then_with(|| a.path.cmp(&b.path)));
+    Ok(files)
+}
+
+fn snapshot_file(path: PathBuf) -> SnapshotFile {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let named_time = snapshot_time(&name)
+        .or_else(|| name.strip_suffix(".json").and_then(|stem| stem.parse().ok()));
+    SnapshotFile { path, named_time }
+}
+
+/// Reads and parses a marker file, decompressing it if it is gzipped.
+pub fn read_marker_file(path: &Path) -> Result<MarkerFile, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path)?;
+    // Gzip streams start with the magic bytes 1f 8b
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut json = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+        Ok(serde_json::from_slice(&json)?)
+    } else {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// Picks the time a snapshot represents: the archive file name, then the
+/// marker file's own millisecond timestamp, then the file's modification time.
+pub fn snapshot_timestamp(file: &SnapshotFile, marker_file: &MarkerFile) -> i64 {
+    file.named_time
+        .or_else(|| marker_file.timestamp.map(|ms| ms / 1000))
+        .or_else(|| {
+            let modified = std::fs::metadata(&file.path).and_then(|m| m.modified()).ok()?;
+            Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+        })
+        .unwrap_or(0)
+}
+
+/// Ingests every snapshot at `path` in order, as if each had just been fetched
+/// at its own timestamp. Unparseable files are reported and skipped.
+pub async fn replay(
+    source: &SourceConfig,
+    path: &Path,
+    db_client: &Client,
+    history: &HistoryConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let files = list_snapshots(path)?;
+    println!("[{}] Replaying {} snapshot files from {}", source.name, files.len(), path.display());
+    let mut state = SourceState::new(history.clone());
+
+    for file in files {
+        let marker_file = match read_marker_file(&file.path) {
+            Ok(marker_file) => marker_file,
+            Err(e) => {
+                println!("[{}] Skipping {}: {}", source.name, file.path.display(), e);
+                continue;
+            }
+        };
+        let timestamp = snapshot_timestamp(&file, &marker_file);
+        println!("[{}] Replaying {} at {}", source.name, file.path.display(), timestamp);
+        let (stats, counts) = data::ingest_marker_file(source, &marker_file, timestamp, db_client, &mut state).await?;
+        data::report_cycle(source, &mut state, stats, counts);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::drift::ParseStats;
+    use crate::services::geometry::Polygon;
+    use crate::models::players::PlayerRole;
+    use crate::services::nation::aggregate_nations;
+    use crate::services::player::index_players;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/markers/marker_world.json")
+    }
+
+    #[test]
+    fn replays_fixture_through_pipeline() {
+        let file = list_snapshots(&fixture()).unwrap().remove(0);
+        let marker_file = read_marker_file(&file.path).unwrap();
+        let timestamp = snapshot_timestamp(&file, &marker_file);
+        assert_eq!(timestamp, 1_733_079_600);
+        assert!(marker_file.warnings().is_empty());
+
+        let mut stats = ParseStats::default();
+        let set = &marker_file.sets["towny.markerset"];
+        let mut towns = data::build_towns(&SourceConfig::test_source(), set, timestamp, &mut stats);
+        towns.sort_by(|a, b| a.town_name.cmp(&b.town_name));
+        assert_eq!(stats.towns, 2);
+        assert!(stats.failures.is_empty());
+
+        let astarte = &towns[0];
+        assert_eq!(astarte.town_name, "Astarte");
+        assert_eq!(astarte.nation.as_deref(), Some("Salt & Pepper"));
+        assert_eq!(astarte.bank, 1250.0);
+        assert_eq!(astarte.area, 4352.0);
+        assert_eq!(astarte.chunks(), 17);
+        assert_eq!(astarte.coords, (32.0, 32.0));
+        assert_eq!(astarte.outposts.len(), 1);
+        assert_eq!(astarte.last_updated, timestamp);
+        assert!(astarte.capital);
+
+        // An L-shaped claim whose area centroid falls outside the polygon
+        let lonely = &towns[1];
+        assert_eq!(lonely.nation, None);
+        assert_eq!(lonely.area, 1792.0);
+        let polygon = Polygon::from_xz(
+            &set.areas["Lonely__0"].x,
+            &set.areas["Lonely__0"].z,
+        );
+        assert!(polygon.contains(lonely.coords));
+
+        // Only Astarte belongs to a nation
+        let nations = aggregate_nations(&towns, timestamp);
+        assert_eq!(nations.len(), 1);
+        let nation = &nations[0];
+        assert_eq!(nation.nation_name, "Salt & Pepper");
+        assert_eq!(nation.capital.as_deref(), Some("Astarte"));
+        assert_eq!(nation.leader.as_deref(), Some("Steve"));
+        assert_eq!((nation.residents, nation.bank, nation.upkeep), (2, 1250.0, 20.0));
+        assert_eq!(nation.next_falling_town.as_deref(), Some("Astarte"));
+        // 63 upkeeps paid after the next 19:00 UTC collection, which is on Dec 2
+        assert_eq!(nation.next_fall, Some(1_733_166_000 + 63 * 86_400));
+
+        let players = index_players(&towns, &nations, timestamp);
+        let roles: Vec<_> = players.iter().map(|p| (p.player_name.as_str(), p.role)).collect();
+        assert_eq!(roles, [
+            ("Alex", Some(PlayerRole::Resident)),
+            ("Hermit", Some(PlayerRole::Mayor)),
+            ("Steve", Some(PlayerRole::Leader)),
+        ]);
+        assert_eq!(players[0].town.as_deref(), Some("Astarte"));
+        assert_eq!(players[0].nation.as_deref(), Some("Salt & Pepper"));
+    }
+
+    #[test]
+    fn reads_archived_snapshots() {
+        let dir = std::env::temp_dir().join(format!("ccnetizen-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw = std::fs::read(fixture()).unwrap();
+        for time in [1_733_083_200, 1_733_079_600] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&raw).unwrap();
+            std::fs::write(dir.join(format!("{}{}", time, SNAPSHOT_EXTENSION)), encoder.finish().unwrap()).unwrap();
+        }
+
+        let files = list_snapshots(&dir).unwrap();
+        let times: Vec<_> = files.iter().map(|f| f.named_time).collect();
+        assert_eq!(times, vec![Some(1_733_079_600), Some(1_733_083_200)]);
+        let marker_file = read_marker_file(&files[1].path).unwrap();
+        assert_eq!(snapshot_timestamp(&files[1], &marker_file), 1_733_083_200);
+        assert!(marker_file.sets.contains_key("towny.markerset"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+// Filename: services/search.rs
+// Name matching for autocomplete and "did you mean" suggestions
+
+/// Folds a name for comparison. Underscores render as spaces on the map, so
+/// both spellings of a name compare equal.
+pub fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', " ")
+}
+
+/// The name in `names` equal to `query` once both are normalized.
+pub fn exact<'a>(names: &'a [String], query: &str) -> Option<&'a str> {
+    let query = normalize(query);
+    names.iter().find(|name| normalize(name) == query).map(String::as_str)
+}
+
+/// Up to `limit` names containing `partial`, those starting with it first,
+/// each group in alphabetical order.
+pub fn complete<'a>(names: &'a [String], partial: &str, limit: usize) -> Vec<&'a str> {
+    let partial = normalize(partial);
+    let mut matches: Vec<(bool, String, &str)> = names
+        .iter()
+        .filter_map(|name| {
+            let folded = normalize(name);
+            let position = folded.find(&partial)?;
+            Some((position != 0, folded, name.as_str()))
+        })
+        .collect();
+    matches.sort_unstable();
+    matches.into_iter().take(limit).map(|(_, _, name)| name).collect()
+}
+
+/// Up to `limit` names within a few typos of `query`, closest first. The
+/// number of edits allowed grows with the length of the query.
+pub fn closest<'a>(names: &'a [String], query: &str, limit: usize) -> Vec<&'a str> {
+    let query = normalize(query);
+    let allowed = (query.chars().count() / 3).max(2);
+    let mut matches: Vec<(usize, String, &str)> = names
+        .iter()
+        .filter_map(|name| {
+            let folded = normalize(name);
+            let distance = edit_distance(&query, &folded);
+            (distance <= allowed).then_some((distance, folded, name.as_str()))
+        })
+        .collect();
+    matches.sort_unstable();
+    matches.into_iter().take(limit).map(|(_, _, name)| name).collect()
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        ["Astarte", "New_Rome", "Rome", "Romania", "Aurora"].iter().map(|&n| n.to_owned()).collect()
+    }
+
+    #[test]
+    fn matches_underscores_as_spaces() {
+        let names = names();
+        assert_eq!(exact(&names, "new rome"), Some("New_Rome"));
+        assert_eq!(exact(&names, "NEW_ROME "), Some("New_Rome"));
+        assert_eq!(exact(&names, "New"), None);
+        assert_eq!(complete(&names, "new r", 25), ["New_Rome"]);
+    }
+
+    #[test]
+    fn completes_prefixes_before_substrings() {
+        let names = names();
+        assert_eq!(complete(&names, "rom", 25), ["Romania", "Rome", "New_Rome"]);
+        assert_eq!(complete(&names, "rom", 2), ["Romania", "Rome"]);
+        assert_eq!(complete(&names, "", 2), ["Astarte", "Aurora"]);
+    }
+
+    #[test]
+    fn suggests_names_within_a_few_typos() {
+        let names = names();
+        assert_eq!(closest(&names, "Astrate", 5), ["Astarte"]);
+        assert_eq!(closest(&names, "Rone", 5), ["Rome"]);
+        assert!(closest(&names, "Constantinople", 5).is_empty());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
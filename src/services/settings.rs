@@ -0,0 +1,60 @@
+// Filename: services/settings.rs
+// Per-server settings, read through an in-memory cache
+
+use crate::models::guilds::GuildSettings;
+use crate::repositories::guilds::GuildSettingsRepository;
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Settings of every server seen since startup, keyed by guild ID. Every change
+/// goes through the cache, so it never holds stale settings.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsCache {
+    guilds: Arc<RwLock<HashMap<u64, GuildSettings>>>,
+}
+
+impl SettingsCache {
+    /// The server's settings, loaded from the table the first time they are asked for.
+    /// Servers without stored settings get the defaults.
+    pub async fn get(&self, db_client: &Client, guild_id: u64) -> Result<GuildSettings, Error> {
+        if let Some(settings) = self.guilds.read().ok().and_then(|guilds| guilds.get(&guild_id).cloned()) {
+            return Ok(settings);
+        }
+        let settings = GuildSettingsRepository::new(db_client)
+            .get_settings(guild_id)
+            .await?
+            .unwrap_or_else(|| GuildSettings::new(guild_id));
+        if let Ok(mut guilds) = self.guilds.write() {
+            guilds.insert(guild_id, settings.clone());
+        }
+        Ok(settings)
+    }
+
+    /// Caches the settings of every server that has stored any, so [`Self::all`]
+    /// covers servers that have not used a command since startup.
+    pub async fn load_all(&self, db_client: &Client) -> Result<(), Error> {
+        let stored = GuildSettingsRepository::new(db_client).all_settings().await?;
+        if let Ok(mut guilds) = self.guilds.write() {
+            for settings in stored {
+                guilds.entry(settings.guild_id).or_insert(settings);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every cached server's settings.
+    pub fn all(&self) -> Vec<GuildSettings> {
+        self.guilds.read().map(|guilds| guilds.values().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Stores `settings` and caches them once the write succeeded.
+    pub async fn save(&self, db_client: &Client, settings: GuildSettings) -> Result<(), Error> {
+        GuildSettingsRepository::new(db_client).save_settings(&settings).await?;
+        if let Ok(mut guilds) = self.guilds.write() {
+            guilds.insert(settings.guild_id, settings);
+        }
+        Ok(())
+    }
+}
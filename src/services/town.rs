@@ -1,21 +1,132 @@
-use crate::repositories::towns::TownRepository;
+// Filename: services/town.rs
+// Town lookups and the falling towns list
+
+use crate::config::secret::SourceConfig;
+use crate::repositories::history::TownHistoryRepository;
+use crate::repositories::towns::{TownReadError, TownRepository};
+use crate::models::history::TownSnapshot;
 use crate::models::towns::Town;
 use aws_sdk_dynamodb::Client;
 use aws_sdk_dynamodb::Error;
+use chrono::{DateTime, Duration, Utc};
+use crate::services::upkeep::fall_time;
+
+/// Narrows the falling towns list; every filter left `None` matches all towns.
+#[derive(Debug, Clone, Default)]
+pub struct FallingFilter {
+    pub nation: Option<String>,
+    pub peaceful: Option<bool>,
+    pub min_residents: Option<usize>,
+}
+
+impl FallingFilter {
+    fn matches(&self, town: &Town) -> bool {
+        let nation = match (&self.nation, &town.nation) {
+            (None, _) => true,
+            (Some(wanted), Some(nation)) => wanted.eq_ignore_ascii_case(nation),
+            (Some(_), None) => false,
+        };
+        nation
+            && self.peaceful.is_none_or(|peaceful| town.peaceful == peaceful)
+            && self.min_residents.is_none_or(|min| town.residents.len() >= min)
+    }
+}
 
 pub struct TownService<'a> {
     repository: TownRepository<'a>,
+    history: TownHistoryRepository<'a>,
 }
 
 impl<'a> TownService<'a> {
-    pub fn new(client: &'a Client) -> Self {
+    pub fn new(client: &'a Client, source: &SourceConfig) -> Self {
         Self {
-            repository: TownRepository::new(client),
+            repository: TownRepository::new(client, source),
+            history: TownHistoryRepository::new(client, source),
         }
     }
 
-    pub async fn get_town_info(&self, town_name: &str) -> Result<Option<Town>, Error> {
+    pub async fn get_town_info(&self, town_name: &str) -> Result<Option<Town>, TownReadError> {
         let town_name_lower = town_name.to_lowercase();
         self.repository.get_town(&town_name_lower).await
     }
+
+    /// History points of the town with `town_id` between `from` and `to`, oldest first.
+    pub async fn get_history(&self, town_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<TownSnapshot>, Error> {
+        self.history.get_history(town_id, from.timestamp(), to.timestamp()).await
+    }
+}
+
+/// Every standing town among `towns` matching `filter` whose bank runs out
+/// within `hours` of `now`, soonest first.
+pub fn falling_towns<'a>(
+    towns: impl IntoIterator<Item = &'a Town>,
+    now: DateTime<Utc>,
+    hours: i64,
+    filter: &FallingFilter,
+) -> Vec<(Town, DateTime<Utc>)> {
+    falling_within(towns.into_iter().cloned(), now, now + Duration::hours(hours), filter)
+}
+
+/// Pairs every standing town matching `filter` with the upkeep it falls at,
+/// keeping those that fall no later than `until`, soonest first.
+fn falling_within(
+    towns: impl IntoIterator<Item = Town>,
+    now: DateTime<Utc>,
+    until: DateTime<Utc>,
+    filter: &FallingFilter,
+) -> Vec<(Town, DateTime<Utc>)> {
+    let mut falling: Vec<_> = towns
+        .into_iter()
+        .filter(|town| !town.is_fallen() && filter.matches(town))
+        .filter_map(|town| {
+            let falls = fall_time(town.bank, town.upkeep, now)?;
+            (falls <= until).then_some((town, falls))
+        })
+        .collect();
+    falling.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.town_name.cmp(&b.0.town_name)));
+    falling
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::upkeep::TEST_NOON;
+
+    fn town(name: &str, nation: Option<&str>, bank: f64, upkeep: f64, residents: usize) -> Town {
+        let mut town = Town::test_town(name);
+        town.nation = nation.map(str::to_owned);
+        town.bank = bank;
+        town.upkeep = upkeep;
+        town.residents = (0..residents).map(|i| format!("Player{}", i)).collect();
+        town
+    }
+
+    #[test]
+    fn lists_towns_falling_in_window_soonest_first() {
+        let now = DateTime::from_timestamp(TEST_NOON, 0).unwrap();
+        let mut fallen = town("Ruin", None, 0.0, 10.0, 1);
+        fallen.fallen_at = Some(0);
+        let towns = vec![
+            town("Broke", Some("Rome"), 0.0, 10.0, 1),
+            town("TwoDays", Some("Rome"), 15.0, 10.0, 4),
+            town("Rich", Some("Rome"), 1000.0, 10.0, 8),
+            town("Free", None, 0.0, 0.0, 1),
+            town("Nomad", None, 5.0, 10.0, 2),
+            fallen,
+        ];
+
+        let names = |filter: &FallingFilter, hours: i64| -> Vec<String> {
+            falling_within(towns.clone(), now, now + Duration::hours(hours), filter)
+                .into_iter()
+                .map(|(town, _)| town.town_name)
+                .collect()
+        };
+        assert_eq!(names(&FallingFilter::default(), 24), ["Broke"]);
+        assert_eq!(names(&FallingFilter::default(), 72), ["Broke", "Nomad", "TwoDays"]);
+
+        let rome = FallingFilter { nation: Some("rome".to_owned()), ..Default::default() };
+        assert_eq!(names(&rome, 72), ["Broke", "TwoDays"]);
+        let populous = FallingFilter { min_residents: Some(2), ..Default::default() };
+        assert_eq!(names(&populous, 72), ["Nomad", "TwoDays"]);
+    }
 }
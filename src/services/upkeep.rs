@@ -0,0 +1,35 @@
+// Filename: services/upkeep.rs
+// Daily upkeep schedule and when a town's bank runs dry
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Hour of the day, in UTC, at which Towny collects upkeep.
+pub const UPKEEP_HOUR: u32 = 19;
+
+/// Noon, so the next upkeep is at 19:00 the same day.
+#[cfg(test)]
+pub const TEST_NOON: i64 = 1_733_054_400;
+
+/// The first upkeep collection strictly after `now`.
+pub fn next_upkeep(now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now
+        .date_naive()
+        .and_hms_opt(UPKEEP_HOUR, 0, 0)
+        .expect("Invalid upkeep time")
+        .and_utc();
+    if now < today {
+        today
+    } else {
+        today + Duration::days(1)
+    }
+}
+
+/// The upkeep collection a town with `bank` can no longer pay and falls at,
+/// or `None` when it pays no upkeep and so never falls.
+pub fn fall_time(bank: f64, upkeep: f64, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if upkeep <= 0.0 {
+        return None;
+    }
+    let paid_days = (bank / upkeep).ceil().max(0.0) as i64;
+    Some(next_upkeep(now) + Duration::days(paid_days))
+}
@@ -0,0 +1,421 @@
+// Filename: services/watch.rs
+// Watch subscriptions: caching them, checking them after each cycle and delivering notifications
+
+use crate::commands::pagination::{truncate_lines, DESCRIPTION_LIMIT};
+use crate::config::secret::SourceConfig;
+use crate::models::guilds::GuildSettings;
+use crate::models::towns::Town;
+use crate::models::watches::{Watch, WatchKind};
+use crate::repositories::watches::WatchRepository;
+use crate::services::locale::format_money;
+use crate::services::search::normalize;
+use crate::services::upkeep::fall_time;
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::Error;
+use chrono::{DateTime, Duration};
+use poise::serenity_prelude::{ChannelId, Colour, CreateEmbed, CreateMessage, Http, UserId};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+/// Every source's watches, loaded from its table the first time they are asked
+/// for. Every subscription change goes through the cache, so it never holds
+/// stale watches.
+#[derive(Debug, Clone, Default)]
+pub struct WatchCache {
+    sources: Arc<RwLock<HashMap<String, SourceWatches>>>,
+}
+
+#[derive(Debug, Default)]
+struct SourceWatches {
+    /// Every watch, keyed by target and subscriber
+    watches: HashMap<(String, String), Watch>,
+    /// Keys of each user's watches, in order
+    by_user: HashMap<u64, BTreeSet<(String, String)>>,
+}
+
+impl SourceWatches {
+    fn insert(&mut self, watch: Watch) {
+        let key = (watch.target(), watch.subscriber());
+        self.by_user.entry(watch.user_id).or_default().insert(key.clone());
+        self.watches.insert(key, watch);
+    }
+
+    fn remove(&mut self, key: &(String, String)) {
+        if let Some(watch) = self.watches.remove(key) {
+            if let Some(keys) = self.by_user.get_mut(&watch.user_id) {
+                keys.remove(key);
+            }
+        }
+    }
+}
+
+impl WatchCache {
+    /// Reads the source's watches from its table unless they are cached already.
+    async fn load(&self, db_client: &Client, source: &SourceConfig) -> Result<(), Error> {
+        if self.sources.read().is_ok_and(|sources| sources.contains_key(&source.name)) {
+            return Ok(());
+        }
+        let mut loaded = SourceWatches::default();
+        for watch in WatchRepository::new(db_client, source).all_watches().await? {
+            loaded.insert(watch);
+        }
+        if let Ok(mut sources) = self.sources.write() {
+            sources.entry(source.name.clone()).or_insert(loaded);
+        }
+        Ok(())
+    }
+
+    /// Stores `watch`, replacing the subscriber's earlier watch of the same target.
+    pub async fn subscribe(&self, db_client: &Client, source: &SourceConfig, watch: Watch) -> Result<(), Error> {
+        self.load(db_client, source).await?;
+        WatchRepository::new(db_client, source).save_watch(&watch).await?;
+        if let Ok(mut sources) = self.sources.write() {
+            sources.entry(source.name.clone()).or_default().insert(watch);
+        }
+        Ok(())
+    }
+
+    /// Removes the watch stored under `target` and `subscriber`, see
+    /// [`Watch::target_key`]. Returns whether there was one.
+    pub async fn unsubscribe(&self, db_client: &Client, source: &SourceConfig, target: String, subscriber: String) -> Result<bool, Error> {
+        self.load(db_client, source).await?;
+        let removed = WatchRepository::new(db_client, source).delete_watch(&target, &subscriber).await?;
+        if let Ok(mut sources) = self.sources.write() {
+            sources.entry(source.name.clone()).or_default().remove(&(target, subscriber));
+        }
+        Ok(removed)
+    }
+
+    /// Every watch the user set up, for themselves or for a channel, by target.
+    pub async fn watches_of(&self, db_client: &Client, source: &SourceConfig, user_id: u64) -> Result<Vec<Watch>, Error> {
+        self.load(db_client, source).await?;
+        let Ok(sources) = self.sources.read() else {
+            return Ok(Vec::new());
+        };
+        let Some(cached) = sources.get(&source.name) else {
+            return Ok(Vec::new());
+        };
+        Ok(cached.by_user
+            .get(&user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|key| cached.watches.get(key).cloned())
+            .collect())
+    }
+
+    /// Every watch of every target of the source.
+    pub async fn all_watches(&self, db_client: &Client, source: &SourceConfig) -> Result<Vec<Watch>, Error> {
+        self.load(db_client, source).await?;
+        Ok(self.sources
+            .read()
+            .ok()
+            .and_then(|sources| sources.get(&source.name).map(|cached| cached.watches.values().cloned().collect()))
+            .unwrap_or_default())
+    }
+}
+
+/// Changes to the towns one watch covers, ready to be sent to its subscriber.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub user_id: u64,
+    pub channel_id: Option<u64>,
+    pub title: String,
+    pub lines: Vec<String>,
+    /// Embed colour of the server the watch was set up in
+    pub colour: Colour,
+}
+
+/// Town watches follow the town's ID through renames, and fall back to the
+/// name for watches stored without one.
+fn covers(watch: &Watch, town: &Town) -> bool {
+    match watch.kind {
+        WatchKind::Town => match &watch.town_id {
+            Some(town_id) => town.town_id == *town_id,
+            None => normalize(&town.town_name) == normalize(&watch.name),
+        },
+        WatchKind::Nation => town.nation.as_deref().is_some_and(|nation| normalize(nation) == normalize(&watch.name)),
+    }
+}
+
+/// The towns any of `watches` covers, keyed by `town_name_lower`.
+pub fn watched_towns<'a>(watches: &[Watch], towns: impl IntoIterator<Item = &'a Town>) -> HashMap<String, Town> {
+    towns
+        .into_iter()
+        .filter(|town| watches.iter().any(|watch| covers(watch, town)))
+        .map(|town| (town.town_name_lower.clone(), town.clone()))
+        .collect()
+}
+
+/// Compares the watched towns as seen at `previous_at` with `current`, as of
+/// `now`, and builds one notification per watch that has something to report,
+/// styled with the settings in `guilds` of the server the watch was set up in.
+/// Towns not seen before are skipped, so a new watch starts quietly. Thresholds
+/// only notify when they are crossed, not on every cycle spent past them.
+pub fn evaluate(
+    watches: &[Watch],
+    previous: &HashMap<String, Town>,
+    previous_at: i64,
+    current: &HashMap<String, Town>,
+    now: i64,
+    guilds: &HashMap<u64, GuildSettings>,
+) -> Vec<Notification> {
+    let mut towns: Vec<&Town> = current.values().collect();
+    towns.sort_by(|a, b| a.town_name.cmp(&b.town_name));
+
+    watches
+        .iter()
+        .filter_map(|watch| {
+            let settings = watch.guild_id.and_then(|guild_id| guilds.get(&guild_id));
+            let locale = settings.and_then(|settings| settings.locale.as_deref());
+            let lines: Vec<String> = towns
+                .iter()
+                .filter(|town| covers(watch, town))
+                .filter_map(|town| Some((previous.get(&town.town_name_lower)?, *town)))
+                .flat_map(|(before, after)| changes(watch, before, previous_at, after, now, locale))
+                .collect();
+            (!lines.is_empty()).then(|| Notification {
+                user_id: watch.user_id,
+                channel_id: watch.channel_id,
+                title: match watch.kind {
+                    WatchKind::Town => format!("Watched town {}", watch.name),
+                    WatchKind::Nation => format!("Watched nation {}", watch.name),
+                },
+                lines,
+                colour: settings.map_or(Colour::BLITZ_BLUE, GuildSettings::colour),
+            })
+        })
+        .collect()
+}
+
+/// What changed in one town between `before` and `after` that `watch` asks about.
+fn changes(watch: &Watch, before: &Town, before_at: i64, after: &Town, now: i64, locale: Option<&str>) -> Vec<String> {
+    let name = &after.town_name;
+    let mut lines = Vec::new();
+    if after.is_fallen() {
+        if watch.fallen && !before.is_fallen() {
+            lines.push(match (&after.renamed_to, &after.merged_into) {
+                (Some(new_name), _) => format!("**{}** was renamed to {}", name, new_name),
+                (_, Some(target)) => format!("**{}** merged into {}", name, target),
+                _ => format!("**{}** has fallen", name),
+            });
+        }
+        return lines;
+    }
+
+    if let Some(threshold) = watch.bank_below {
+        if before.bank >= threshold && after.bank < threshold {
+            lines.push(format!(
+                "**{}** bank dropped to {}, below {}",
+                name,
+                format_money(after.bank, locale),
+                format_money(threshold, locale)
+            ));
+        }
+    }
+    if let Some(hours) = watch.falls_within_hours {
+        let within = |town: &Town, at: i64| {
+            let at = DateTime::from_timestamp(at, 0)?;
+            fall_time(town.bank, town.upkeep, at).filter(|falls| *falls <= at + Duration::hours(hours as i64))
+        };
+        if let (Some(falls), None) = (within(after, now), within(before, before_at)) {
+            lines.push(format!("**{}** falls <t:{}:R> unless its bank is topped up", name, falls.timestamp()));
+        }
+    }
+    if watch.residents {
+        let was: HashSet<&String> = before.residents.iter().collect();
+        let is: HashSet<&String> = after.residents.iter().collect();
+        let mut joined: Vec<&str> = is.difference(&was).map(|s| s.as_str()).collect();
+        let mut left: Vec<&str> = was.difference(&is).map(|s| s.as_str()).collect();
+        joined.sort_unstable();
+        left.sort_unstable();
+        if !joined.is_empty() {
+            lines.push(format!("**{}** gained residents: {}", name, joined.join(", ")));
+        }
+        if !left.is_empty() {
+            lines.push(format!("**{}** lost residents: {}", name, left.join(", ")));
+        }
+    }
+    if watch.mayor && before.mayor != after.mayor {
+        lines.push(format!("**{}** mayor changed from {} to {}", name, before.mayor, after.mayor));
+    }
+    if watch.board && before.board != after.board {
+        lines.push(format!("**{}** board changed to: {}", name, after.board));
+    }
+    lines
+}
+
+/// Sends notifications to Discord outside of any command.
+#[derive(Clone)]
+pub struct Notifier {
+    http: Arc<Http>,
+}
+
+impl Notifier {
+    pub fn new(http: Arc<Http>) -> Self {
+        Self { http }
+    }
+
+    /// Posts the notification in its channel, or direct messages the subscriber.
+    pub async fn deliver(&self, notification: &Notification) -> Result<(), poise::serenity_prelude::Error> {
+        let description = truncate_lines(&notification.lines, DESCRIPTION_LIMIT, " changes");
+        let message = CreateMessage::new().embed(
+            CreateEmbed::default()
+                .title(notification.title.clone())
+                .description(description)
+                .colour(notification.colour),
+        );
+
+        let channel = match notification.channel_id {
+            Some(channel_id) => ChannelId::new(channel_id),
+            None => UserId::new(notification.user_id).create_dm_channel(&self.http).await?.id,
+        };
+        channel.send_message(&self.http, message).await?;
+        Ok(())
+    }
+
+    /// Posts `embed` in the channel with `channel_id`.
+    pub async fn post(&self, channel_id: u64, embed: CreateEmbed) -> Result<(), poise::serenity_prelude::Error> {
+        ChannelId::new(channel_id).send_message(&self.http, CreateMessage::new().embed(embed)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::upkeep::TEST_NOON;
+
+    fn watch(kind: WatchKind, name: &str) -> Watch {
+        Watch {
+            kind,
+            name: name.to_owned(),
+            town_id: None,
+            user_id: 1,
+            channel_id: None,
+            guild_id: None,
+            bank_below: Some(100.0),
+            falls_within_hours: Some(48),
+            residents: true,
+            mayor: true,
+            board: true,
+            fallen: true,
+            created_at: 0,
+        }
+    }
+
+    fn towns(towns: &[Town]) -> HashMap<String, Town> {
+        towns.iter().map(|town| (town.town_name_lower.clone(), town.clone())).collect()
+    }
+
+    #[test]
+    fn reports_watched_changes_once() {
+        let now = TEST_NOON;
+        let mut before = Town::test_town("New_Rome");
+        before.nation = Some("Rome".to_owned());
+        before.bank = 500.0;
+        let mut after = before.clone();
+        after.bank = 5.0;
+        after.mayor = "Alex".to_owned();
+        after.residents.push("Notch".to_owned());
+        let mut quiet = Town::test_town("Gaul");
+        quiet.nation = Some("Rome".to_owned());
+
+        let watches = [watch(WatchKind::Town, "new rome"), watch(WatchKind::Nation, "ROME")];
+        let previous = watched_towns(&watches, [&before, &quiet]);
+        let current = towns(&[after.clone(), quiet.clone()]);
+        let notifications = evaluate(&watches, &previous, now - 60, &current, now, &HashMap::new());
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0].lines, [
+            "**New_Rome** bank dropped to $5.00, below $100.00".to_owned(),
+            format!("**New_Rome** falls <t:{}:R> unless its bank is topped up", now + 31 * 3600),
+            "**New_Rome** gained residents: Notch".to_owned(),
+            "**New_Rome** mayor changed from Steve to Alex".to_owned(),
+        ]);
+        assert_eq!(notifications[1].lines, notifications[0].lines);
+
+        // Nothing new the next cycle, even though the bank is still low
+        let notifications = evaluate(&watches, &current, now, &current, now + 60, &HashMap::new());
+        assert!(notifications.is_empty());
+    }
+
+    #[test]
+    fn reports_falls_and_skips_unseen_towns() {
+        let before = Town::test_town("Astarte");
+        let mut after = before.clone();
+        after.fallen_at = Some(100);
+        after.merged_into = Some("Rome".to_owned());
+        let watches = [watch(WatchKind::Town, "Astarte")];
+
+        let notifications = evaluate(&watches, &towns(&[before]), 0, &towns(&[after.clone()]), 100, &HashMap::new());
+        assert_eq!(notifications[0].lines, ["**Astarte** merged into Rome"]);
+        assert!(evaluate(&watches, &HashMap::new(), 0, &towns(&[after]), 100, &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn indexes_watches_by_user() {
+        let mut cached = SourceWatches::default();
+        let mut channel = watch(WatchKind::Nation, "Rome");
+        channel.channel_id = Some(7);
+        let mut other = watch(WatchKind::Town, "Astarte");
+        other.user_id = 2;
+        for watch in [watch(WatchKind::Town, "Astarte"), channel.clone(), other] {
+            cached.insert(watch);
+        }
+        let keys = |cached: &SourceWatches, user_id| cached.by_user[&user_id].iter().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&cached, 1), [
+            ("nation:rome".to_owned(), "channel:7:user:1".to_owned()),
+            ("town:astarte".to_owned(), "user:1".to_owned()),
+        ]);
+
+        cached.remove(&(channel.target(), channel.subscriber()));
+        assert_eq!(keys(&cached, 1), [("town:astarte".to_owned(), "user:1".to_owned())]);
+        assert_eq!(cached.watches.len(), 2);
+    }
+
+    #[test]
+    fn follows_renamed_towns_by_id() {
+        let before = Town::test_town("Astarte");
+        let mut watch = watch(WatchKind::Town, "Astarte");
+        watch.town_id = Some(before.town_id.clone());
+        let watches = [watch];
+
+        // The old name's row falls with the new name, the new one keeps the ID
+        let mut old = before.clone();
+        old.fallen_at = Some(100);
+        old.renamed_to = Some("Astartia".to_owned());
+        let mut renamed = before.clone();
+        renamed.town_name = "Astartia".to_owned();
+        renamed.town_name_lower = "astartia".to_owned();
+        let previous = watched_towns(&watches, [&before]);
+        let current = watched_towns(&watches, [&old, &renamed]);
+        let notifications = evaluate(&watches, &previous, 0, &current, 100, &HashMap::new());
+        assert_eq!(notifications[0].lines, ["**Astarte** was renamed to Astartia"]);
+
+        // Changes under the new name are still reported
+        let mut changed = renamed.clone();
+        changed.mayor = "Alex".to_owned();
+        let notifications = evaluate(&watches, &current, 100, &watched_towns(&watches, [&old, &changed]), 200, &HashMap::new());
+        assert_eq!(notifications[0].lines, ["**Astartia** mayor changed from Steve to Alex"]);
+    }
+
+    #[test]
+    fn styles_notifications_for_the_watch_server() {
+        let mut before = Town::test_town("Astarte");
+        before.bank = 6000.0;
+        let mut after = before.clone();
+        after.bank = -1234.5;
+        let mut styled = watch(WatchKind::Town, "Astarte");
+        styled.guild_id = Some(42);
+        styled.bank_below = Some(5000.0);
+        let mut settings = GuildSettings::new(42);
+        settings.locale = Some("de".to_owned());
+        settings.embed_colour = Some(0xFF0000);
+        let guilds = HashMap::from([(42, settings)]);
+
+        let watches = [styled, watch(WatchKind::Town, "Astarte")];
+        let notifications = evaluate(&watches, &towns(&[before]), 0, &towns(&[after]), 100, &guilds);
+        assert_eq!(notifications[0].lines[0], "**Astarte** bank dropped to -$1.234,50, below $5.000,00");
+        assert_eq!(notifications[0].colour, Colour::new(0xFF0000));
+        assert_eq!(notifications[1].colour, Colour::BLITZ_BLUE);
+    }
+}